@@ -8,10 +8,24 @@ struct TestApp {}
 
 impl App for TestApp {
     fn update(&mut self, gfx: &mut Graphics) {
-        gfx.fill_rect(50.0, 70.0, 200.0, 300.0, &Color::rgba(1.0, 0.0, 0.0, 0.5));
-        gfx.draw_rect(50.0, 70.0, 200.0, 300.0, &Color::rgba(1.0, 1.0, 1.0, 0.1));
-        gfx.draw_line(50.0, 70.0, 200.0, 300.0, &Color::rgba(1.0, 1.0, 1.0, 0.1));
-        gfx.fill_rect(120.0, 120.0, 400.0, 400.0, &Color::rgba(0.0, 1.0, 0.0, 0.5));
+        gfx.fill_rect(50.0, 70.0, 200.0, 300.0, Color::rgba(1.0, 0.0, 0.0, 0.5));
+        gfx.draw_rect(
+            50.0,
+            70.0,
+            200.0,
+            300.0,
+            &StrokeStyle::default(),
+            Color::rgba(1.0, 1.0, 1.0, 0.1),
+        );
+        gfx.draw_line(
+            50.0,
+            70.0,
+            200.0,
+            300.0,
+            &StrokeStyle::default(),
+            Color::rgba(1.0, 1.0, 1.0, 0.1),
+        );
+        gfx.fill_rect(120.0, 120.0, 400.0, 400.0, Color::rgba(0.0, 1.0, 0.0, 0.5));
 
         for i in 0..100 {
             gfx.draw_text(
@@ -31,7 +45,7 @@ impl App for TestApp {
         builder.line_to(point(300.0, 400.0));
         builder.close();
         let path = builder.build();
-        gfx.fill_path(path, &Color::rgba(0.0, 1.0, 1.0, 1.0));
+        gfx.fill_path(path, Color::rgba(0.0, 1.0, 1.0, 1.0));
 
         let mut builder = Path::builder();
         builder.begin(point(100.0, 100.0));
@@ -39,7 +53,11 @@ impl App for TestApp {
         builder.line_to(point(300.0, 400.0));
         builder.close();
         let path = builder.build();
-        gfx.draw_path(path, &Color::rgba(0.0, 0.0, 0.0, 1.0));
+        gfx.draw_path(
+            path,
+            &StrokeStyle::default(),
+            Color::rgba(0.0, 0.0, 0.0, 1.0),
+        );
 
         gfx.draw_fitted_text_line(
             "the quick brown fox jumps over the lazy dog",
@@ -66,6 +84,7 @@ fn main() {
         title: "renderer test".into(),
         width: 800,
         height: 600,
+        msaa_samples: 4,
         ..Default::default()
     };
     let app = TestApp {};
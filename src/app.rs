@@ -15,6 +15,10 @@ pub struct AppSettings {
     pub title: String,
     pub width: u32,
     pub height: u32,
+    /// Samples per pixel for anti-aliasing: 1 (off), 2, 4 or 8.
+    pub msaa_samples: u32,
+    /// Color each frame starts from, see `Graphics::set_clear_color`.
+    pub clear_color: Option<Color>,
 }
 impl Default for AppSettings {
     fn default() -> Self {
@@ -22,6 +26,8 @@ impl Default for AppSettings {
             title: "App".into(),
             width: 800,
             height: 600,
+            msaa_samples: 1,
+            clear_color: Some(Color::rgba(0.0, 0.0, 0.0, 0.0)),
         }
     }
 }
@@ -32,7 +38,12 @@ pub trait App {
     fn handle_event(&mut self, _gfx: &mut Graphics, _event: &WindowEvent) {}
 }
 
-async fn run_async(event_loop: EventLoop<()>, window: &Window, mut app: impl App) {
+async fn run_async(
+    event_loop: EventLoop<()>,
+    window: &Window,
+    settings: AppSettings,
+    mut app: impl App,
+) {
     let mut size = window.inner_size();
     size.width = size.width.max(1);
     size.height = size.height.max(1);
@@ -53,7 +64,36 @@ async fn run_async(event_loop: EventLoop<()>, window: &Window, mut app: impl App
         .await
         .unwrap();
     let swapchain_capabilities = surface.get_capabilities(&adapter);
-    let swapchain_format = swapchain_capabilities.formats[0];
+    // prefer an sRGB format, and otherwise draw through an sRGB view of the
+    // first one, so colors blend in linear light wherever the adapter allows
+    let swapchain_format = swapchain_capabilities
+        .formats
+        .iter()
+        .copied()
+        .find(|format| format.is_srgb())
+        .unwrap_or(swapchain_capabilities.formats[0]);
+    let srgb_format = swapchain_format.add_srgb_suffix();
+    let view_format = if srgb_format == swapchain_format {
+        if !swapchain_format.is_srgb() {
+            eprintln!(
+                "no sRGB format for {:?}, colors will blend in gamma space",
+                swapchain_format
+            );
+        }
+        swapchain_format
+    } else if !adapter
+        .get_downlevel_capabilities()
+        .flags
+        .contains(wgpu::DownlevelFlags::SURFACE_VIEW_FORMATS)
+    {
+        eprintln!(
+            "surface view formats not supported by adapter, drawing to {:?} and blending in gamma space",
+            swapchain_format
+        );
+        swapchain_format
+    } else {
+        srgb_format
+    };
 
     let config = wgpu::SurfaceConfiguration {
         usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
@@ -62,7 +102,11 @@ async fn run_async(event_loop: EventLoop<()>, window: &Window, mut app: impl App
         height: size.height,
         present_mode: wgpu::PresentMode::Fifo,
         alpha_mode: swapchain_capabilities.alpha_modes[0],
-        view_formats: vec![swapchain_format],
+        view_formats: if view_format == swapchain_format {
+            vec![]
+        } else {
+            vec![view_format]
+        },
     };
 
     surface.configure(&device, &config);
@@ -74,8 +118,23 @@ async fn run_async(event_loop: EventLoop<()>, window: &Window, mut app: impl App
         window.scale_factor() as f32,
     );
 
-    // add blank texture at uv coords (0, 0)
-    gfx.add_texture(&[0xff; 4], 1, 1);
+    // the stencil buffer is multisampled along with the color target
+    let msaa_supported = [view_format, STENCIL_FORMAT].iter().all(|&format| {
+        adapter
+            .get_texture_format_features(format)
+            .flags
+            .sample_count_supported(settings.msaa_samples)
+    });
+    if msaa_supported {
+        gfx.set_msaa_samples(settings.msaa_samples);
+    } else {
+        eprintln!(
+            "{}x msaa not supported by adapter, disabling msaa",
+            settings.msaa_samples
+        );
+    }
+
+    gfx.set_clear_color(settings.clear_color);
 
     app.init(&mut gfx);
 
@@ -102,9 +161,10 @@ async fn run_async(event_loop: EventLoop<()>, window: &Window, mut app: impl App
                     }
                     WindowEvent::RedrawRequested => {
                         let frame = surface.get_current_texture().unwrap();
-                        let view = frame
-                            .texture
-                            .create_view(&wgpu::TextureViewDescriptor::default());
+                        let view = frame.texture.create_view(&wgpu::TextureViewDescriptor {
+                            format: Some(gfx.view_format()),
+                            ..Default::default()
+                        });
 
                         gfx.render(&view);
                         frame.present();
@@ -129,5 +189,5 @@ pub fn run(settings: AppSettings, app: impl App) {
     event_loop.set_control_flow(ControlFlow::Poll);
     window.set_title(&settings.title);
     let _ = window.request_inner_size(LogicalSize::new(settings.width, settings.height));
-    pollster::block_on(run_async(event_loop, &window, app));
+    pollster::block_on(run_async(event_loop, &window, settings, app));
 }
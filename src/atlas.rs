@@ -0,0 +1,300 @@
+use crate::graphics::{Rect, TextureId};
+use std::collections::HashMap;
+
+#[derive(Clone, Copy)]
+struct Segment {
+    x: u32,
+    y: u32,
+    width: u32,
+}
+
+/// Skyline bottom-left rectangle packer. The skyline is a list of segments
+/// covering the full width of the page, each recording the height packed so
+/// far below it.
+pub struct SkylinePacker {
+    width: u32,
+    height: u32,
+    skyline: Vec<Segment>,
+}
+
+impl SkylinePacker {
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            width,
+            height,
+            skyline: vec![Segment { x: 0, y: 0, width }],
+        }
+    }
+
+    /// Finds room for a `width` x `height` rect, preferring the position
+    /// with the lowest top edge, and returns its top-left corner.
+    pub fn alloc(&mut self, width: u32, height: u32) -> Option<(u32, u32)> {
+        if width == 0 || height == 0 {
+            return Some((0, 0));
+        }
+        let mut best: Option<(usize, u32)> = None;
+        for i in 0..self.skyline.len() {
+            if let Some(y) = self.fit(i, width, height) {
+                let better = match best {
+                    Some((_, best_y)) => y < best_y,
+                    None => true,
+                };
+                if better {
+                    best = Some((i, y));
+                }
+            }
+        }
+        let (i, y) = best?;
+        let x = self.skyline[i].x;
+        self.insert(i, x, y + height, width);
+        Some((x, y))
+    }
+
+    /// Height at which a rect starting at segment `i` would rest, if it fits.
+    fn fit(&self, i: usize, width: u32, height: u32) -> Option<u32> {
+        let x = self.skyline[i].x;
+        if x + width > self.width {
+            return None;
+        }
+        let mut y = 0;
+        let mut covered = 0;
+        for segment in &self.skyline[i..] {
+            if covered >= width {
+                break;
+            }
+            y = y.max(segment.y);
+            if y + height > self.height {
+                return None;
+            }
+            covered += segment.width;
+        }
+        Some(y)
+    }
+
+    fn insert(&mut self, i: usize, x: u32, y: u32, width: u32) {
+        self.skyline.insert(i, Segment { x, y, width });
+
+        // trim the segments now covered by the new one
+        let end = x + width;
+        while i + 1 < self.skyline.len() {
+            let next = &mut self.skyline[i + 1];
+            if next.x >= end {
+                break;
+            }
+            let overlap = end - next.x;
+            if overlap >= next.width {
+                self.skyline.remove(i + 1);
+            } else {
+                next.x += overlap;
+                next.width -= overlap;
+                break;
+            }
+        }
+
+        self.skyline.dedup_by(|next, prev| {
+            if next.y == prev.y {
+                prev.width += next.width;
+                true
+            } else {
+                false
+            }
+        });
+    }
+}
+
+/// Space accounting of an atlas page. Space is only reclaimed by repacking
+/// the page into a new one, so freed entries are tracked as dead area.
+pub struct PageSpace {
+    pub width: u32,
+    pub height: u32,
+    packer: SkylinePacker,
+    allocated_area: u64,
+    dead_area: u64,
+}
+
+impl PageSpace {
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            width,
+            height,
+            packer: SkylinePacker::new(width, height),
+            allocated_area: 0,
+            dead_area: 0,
+        }
+    }
+
+    pub fn alloc(&mut self, width: u32, height: u32) -> Option<(u32, u32)> {
+        let pos = self.packer.alloc(width, height)?;
+        self.allocated_area += width as u64 * height as u64;
+        Some(pos)
+    }
+
+    pub fn free(&mut self, width: u32, height: u32) {
+        self.dead_area += width as u64 * height as u64;
+    }
+
+    /// True once the page is more than half allocated and at least half of
+    /// that allocation belongs to freed entries.
+    pub fn is_fragmented(&self) -> bool {
+        let page_area = self.width as u64 * self.height as u64;
+        self.dead_area > 0
+            && self.dead_area * 2 >= self.allocated_area
+            && self.allocated_area * 2 >= page_area
+    }
+}
+
+/// One texture of an atlas.
+pub struct AtlasPage {
+    pub texture: wgpu::Texture,
+    pub texture_id: TextureId,
+    pub space: PageSpace,
+}
+
+impl AtlasPage {
+    pub fn new(texture: wgpu::Texture, texture_id: TextureId, width: u32, height: u32) -> Self {
+        Self {
+            texture,
+            texture_id,
+            space: PageSpace::new(width, height),
+        }
+    }
+
+    /// UV rect of a pixel region of this page.
+    pub fn uv_rect(&self, x: u32, y: u32, width: u32, height: u32) -> Rect {
+        Rect::new(
+            x as f32 / self.space.width as f32,
+            y as f32 / self.space.height as f32,
+            (x + width) as f32 / self.space.width as f32,
+            (y + height) as f32 / self.space.height as f32,
+        )
+    }
+}
+
+/// An entry in one of the pages of an atlas.
+pub struct AtlasEntry {
+    pub page: usize,
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+    /// Frame the entry was last drawn in.
+    pub last_used: u64,
+}
+
+/// Removes the entries that have not been used for more than `max_age`
+/// frames as of `frame`, freeing their space in `pages`.
+pub fn evict_stale<K>(
+    entries: &mut HashMap<K, AtlasEntry>,
+    pages: &mut [&mut PageSpace],
+    frame: u64,
+    max_age: u64,
+) {
+    entries.retain(|_, entry| {
+        let keep = frame - entry.last_used <= max_age;
+        if !keep {
+            pages[entry.page].free(entry.width, entry.height);
+        }
+        keep
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn overlaps(a: (u32, u32, u32, u32), b: (u32, u32, u32, u32)) -> bool {
+        a.0 < b.0 + b.2 && b.0 < a.0 + a.2 && a.1 < b.1 + b.3 && b.1 < a.1 + a.3
+    }
+
+    #[test]
+    fn packs_without_overlap() {
+        let mut packer = SkylinePacker::new(256, 256);
+        let mut rects = vec![];
+        // deterministic pseudo-random sizes
+        let mut seed = 1u32;
+        for _ in 0..200 {
+            seed = seed.wrapping_mul(1664525).wrapping_add(1013904223);
+            let width = 1 + (seed >> 8) % 24;
+            let height = 1 + (seed >> 16) % 24;
+            if let Some((x, y)) = packer.alloc(width, height) {
+                assert!(x + width <= 256 && y + height <= 256);
+                rects.push((x, y, width, height));
+            }
+        }
+        assert!(rects.len() > 100);
+        for (i, &a) in rects.iter().enumerate() {
+            for &b in &rects[i + 1..] {
+                assert!(!overlaps(a, b), "{:?} overlaps {:?}", a, b);
+            }
+        }
+    }
+
+    #[test]
+    fn fails_when_full() {
+        let mut packer = SkylinePacker::new(100, 100);
+        assert_eq!(packer.alloc(101, 1), None);
+        for _ in 0..4 {
+            assert!(packer.alloc(50, 50).is_some());
+        }
+        assert_eq!(packer.alloc(50, 50), None);
+        assert_eq!(packer.alloc(1, 1), None);
+    }
+
+    #[test]
+    fn evicts_entries_unused_for_max_age_frames() {
+        let mut space = PageSpace::new(100, 100);
+        let mut entries = HashMap::new();
+        for (key, last_used) in [(0, 0), (1, 4), (2, 5), (3, 10)] {
+            let (x, y) = space.alloc(25, 100).unwrap();
+            entries.insert(
+                key,
+                AtlasEntry {
+                    page: 0,
+                    x,
+                    y,
+                    width: 25,
+                    height: 100,
+                    last_used,
+                },
+            );
+        }
+
+        evict_stale(&mut entries, &mut [&mut space], 10, 5);
+        let mut kept: Vec<_> = entries.keys().copied().collect();
+        kept.sort();
+        assert_eq!(kept, [2, 3]);
+        // half of the page is dead now
+        assert!(space.is_fragmented());
+    }
+
+    #[test]
+    fn fragmented_once_half_of_allocation_is_freed() {
+        let mut space = PageSpace::new(100, 100);
+        for _ in 0..10 {
+            space.alloc(10, 100).unwrap();
+        }
+        assert!(!space.is_fragmented());
+        for _ in 0..4 {
+            space.free(10, 100);
+        }
+        assert!(!space.is_fragmented());
+        space.free(10, 100);
+        assert!(space.is_fragmented());
+
+        // repacking the live entries into a new page frees up the space
+        let mut repacked = PageSpace::new(100, 100);
+        for _ in 0..5 {
+            repacked.alloc(10, 100).unwrap();
+        }
+        assert!(!repacked.is_fragmented());
+        assert!(repacked.alloc(50, 100).is_some());
+    }
+
+    #[test]
+    fn barely_used_pages_are_not_fragmented() {
+        let mut space = PageSpace::new(100, 100);
+        space.alloc(10, 10).unwrap();
+        space.free(10, 10);
+        assert!(!space.is_fragmented());
+    }
+}
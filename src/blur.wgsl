@@ -0,0 +1,45 @@
+// One direction of a separable gaussian blur, drawn over the whole target.
+
+struct Blur {
+    // offset between samples in uv coordinates
+    step: vec2<f32>,
+    // in texels
+    sigma: f32,
+    radius: i32,
+};
+
+@group(0) @binding(0)
+var<uniform> blur: Blur;
+@group(0) @binding(1)
+var tex: texture_2d<f32>;
+@group(0) @binding(2)
+var samp: sampler;
+
+struct VertexOutput {
+    @location(0) uv: vec2<f32>,
+    @builtin(position) position: vec4<f32>,
+};
+
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOutput {
+    // a triangle that covers the target
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    var result: VertexOutput;
+    result.uv = uv;
+    result.position = vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, 0.0, 1.0);
+    return result;
+}
+
+@fragment
+fn fs_main(vertex: VertexOutput) -> @location(0) vec4<f32> {
+    var sum = textureSampleLevel(tex, samp, vertex.uv, 0.0);
+    var total = 1.0;
+    for (var i = 1; i <= blur.radius; i++) {
+        let weight = exp(-f32(i * i) / (2.0 * blur.sigma * blur.sigma));
+        let offset = blur.step * f32(i);
+        sum += (textureSampleLevel(tex, samp, vertex.uv + offset, 0.0)
+            + textureSampleLevel(tex, samp, vertex.uv - offset, 0.0)) * weight;
+        total += 2.0 * weight;
+    }
+    return sum / total;
+}
@@ -0,0 +1,16 @@
+// Declarations shared by the built-in shader and materials.
+
+struct VertexOutput {
+    @location(0) uv: vec2<f32>,
+    @location(1) color: vec4<f32>,
+    @location(2) pos: vec2<f32>,
+    @builtin(position) position: vec4<f32>,
+};
+
+@group(0) @binding(0)
+var tex: texture_2d<f32>;
+@group(0) @binding(1)
+var samp: sampler;
+// logical size of the target, next to the gradient at binding 0
+@group(1) @binding(1)
+var<uniform> size: vec2<f32>;
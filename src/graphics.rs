@@ -1,24 +1,30 @@
+use crate::atlas::{evict_stale, AtlasEntry, AtlasPage};
 use bytemuck::{Pod, Zeroable};
 use fontdue::layout::{CoordinateSystem, GlyphRasterConfig, Layout, LayoutSettings, TextStyle};
 use fontdue::{Font, FontSettings};
 use lyon::geom::euclid::{Box2D, Point2D};
-use lyon::math::point;
-use lyon::path::{Path, Winding};
+use lyon::geom::Arc;
+use lyon::math::{point, vector, Angle, Point, Transform};
+use lyon::path::builder::BorderRadii;
+use lyon::path::iterator::PathIterator;
+use lyon::path::{Path, PathEvent, Winding};
 use lyon::tessellation::*;
 use serde::Deserialize;
 use std::collections::HashMap;
+use std::ops::Range;
+use std::sync::{self, Mutex};
 use std::{borrow::Cow, mem};
-use wgpu::util::DeviceExt;
 
 #[repr(C)]
 #[derive(Clone, Copy, Pod, Zeroable)]
 pub struct Vertex {
     pub pos: [f32; 2],
     pub uv: [f32; 2],
+    /// Linear, not sRGB, RGBA, such as from `Color::to_linear`.
     pub color: [f32; 4],
 }
 
-#[derive(Debug)]
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub struct Rect {
     pub x1: f32,
     pub y1: f32,
@@ -37,9 +43,26 @@ impl Rect {
             y2: 0.0,
         }
     }
+    /// Overlap of two rects, empty (x2 <= x1 or y2 <= y1) if they are
+    /// disjoint.
+    pub fn intersect(&self, other: &Rect) -> Rect {
+        Rect::new(
+            self.x1.max(other.x1),
+            self.y1.max(other.y1),
+            self.x2.min(other.x2),
+            self.y2.min(other.y2),
+        )
+    }
+    pub fn is_empty(&self) -> bool {
+        self.x2 <= self.x1 || self.y2 <= self.y1
+    }
 }
 
-#[derive(Debug, Deserialize)]
+/// An sRGB-encoded color, as in CSS and image files, with linear alpha.
+/// Colors are converted to linear light with `to_linear` before they reach
+/// the GPU, so blending and gradients are computed in linear light and
+/// written to an sRGB target.
+#[derive(Clone, Copy, Debug, Deserialize)]
 pub struct Color {
     pub r: f32,
     pub g: f32,
@@ -53,6 +76,782 @@ impl Color {
     pub fn rgba(r: f32, g: f32, b: f32, a: f32) -> Self {
         Self { r, g, b, a }
     }
+    /// Same as `rgba`, for spelling out the color space.
+    pub fn from_srgb(r: f32, g: f32, b: f32, a: f32) -> Self {
+        Self::rgba(r, g, b, a)
+    }
+    /// Color of linear-light components.
+    pub fn from_linear(r: f32, g: f32, b: f32, a: f32) -> Self {
+        Self::rgba(linear_to_srgb(r), linear_to_srgb(g), linear_to_srgb(b), a)
+    }
+    /// Linear-light components, as used for vertex colors, gradients and
+    /// clearing.
+    pub fn to_linear(&self) -> [f32; 4] {
+        [
+            srgb_to_linear(self.r),
+            srgb_to_linear(self.g),
+            srgb_to_linear(self.b),
+            self.a,
+        ]
+    }
+}
+
+fn srgb_to_linear(c: f32) -> f32 {
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn linear_to_srgb(c: f32) -> f32 {
+    if c <= 0.0031308 {
+        c * 12.92
+    } else {
+        1.055 * c.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+/// A color at `offset` (0 to 1) along a gradient.
+#[derive(Clone, Copy, Debug)]
+pub struct GradientStop {
+    pub offset: f32,
+    pub color: Color,
+}
+impl GradientStop {
+    pub fn new(offset: f32, color: Color) -> Self {
+        Self { offset, color }
+    }
+}
+
+/// Maximum number of stops of a gradient. The stops with the highest
+/// offsets past this number are ignored.
+pub const MAX_GRADIENT_STOPS: usize = 8;
+
+/// What shapes are filled or stroked with. Gradient coordinates are in the
+/// coordinate space current when the shape is drawn, and the gradient is
+/// extended with its end colors outside of the stops. Drawing with a
+/// gradient without stops panics.
+#[derive(Clone, Debug)]
+pub enum Paint {
+    Solid(Color),
+    /// Varies along the line from (x1, y1) to (x2, y2).
+    LinearGradient {
+        x1: f32,
+        y1: f32,
+        x2: f32,
+        y2: f32,
+        stops: Vec<GradientStop>,
+    },
+    /// Varies from the center (x, y) out to `radius`.
+    RadialGradient {
+        x: f32,
+        y: f32,
+        radius: f32,
+        stops: Vec<GradientStop>,
+    },
+    /// Varies clockwise around (x, y) in one full turn, starting at `angle`
+    /// radians from the x axis.
+    ConicGradient {
+        x: f32,
+        y: f32,
+        angle: f32,
+        stops: Vec<GradientStop>,
+    },
+}
+impl From<Color> for Paint {
+    fn from(color: Color) -> Self {
+        Paint::Solid(color)
+    }
+}
+impl From<&Color> for Paint {
+    fn from(color: &Color) -> Self {
+        Paint::Solid(*color)
+    }
+}
+impl From<&Paint> for Paint {
+    fn from(paint: &Paint) -> Self {
+        paint.clone()
+    }
+}
+
+/// Gradient parameters as laid out in the `Gradient` struct of the shader.
+/// Box shadows share the struct: they use `inverse_transform`, the rect of
+/// the shadow in `params` and the `shadow_*` fields, and leave the stops
+/// unused.
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+struct GradientUniform {
+    /// 0 for none, else one of the `GRADIENT_*` kinds.
+    kind: u32,
+    n_stops: u32,
+    /// Corner radius and standard deviation of the blur of box shadows,
+    /// unused by the other kinds.
+    shadow_radius: f32,
+    shadow_sigma: f32,
+    /// Rows of the transform from drawn positions back to paint space.
+    inverse_transform: [[f32; 4]; 2],
+    params: [f32; 4],
+    offsets: [[f32; 4]; MAX_GRADIENT_STOPS / 4],
+    colors: [[f32; 4]; MAX_GRADIENT_STOPS],
+}
+
+/// How `draw_*` methods outline shapes.
+#[derive(Clone, Debug)]
+pub struct StrokeStyle {
+    pub width: f32,
+    pub cap: LineCap,
+    pub join: LineJoin,
+    /// Ratio of miter length to line width past which miter joins are
+    /// beveled. Clamped to at least 1.
+    pub miter_limit: f32,
+    /// Alternating dash and gap lengths. An odd-length pattern is repeated
+    /// to make it even. An empty pattern draws a solid line, and so does
+    /// one that is too short for the path to be split into its dashes.
+    pub dashes: Vec<f32>,
+    /// Distance into the dash pattern at which each subpath starts.
+    pub dash_offset: f32,
+}
+impl StrokeStyle {
+    pub fn new(width: f32) -> Self {
+        Self {
+            width,
+            ..Default::default()
+        }
+    }
+    pub fn dashed(width: f32, dashes: &[f32]) -> Self {
+        Self {
+            width,
+            dashes: dashes.to_vec(),
+            ..Default::default()
+        }
+    }
+    fn options(&self, tolerance: f32) -> StrokeOptions {
+        StrokeOptions::tolerance(tolerance)
+            .with_line_width(self.width)
+            .with_line_cap(self.cap)
+            .with_line_join(self.join)
+            .with_miter_limit(self.miter_limit.max(StrokeOptions::MINIMUM_MITER_LIMIT))
+    }
+}
+impl Default for StrokeStyle {
+    fn default() -> Self {
+        Self {
+            width: StrokeOptions::DEFAULT_LINE_WIDTH,
+            cap: StrokeOptions::DEFAULT_LINE_CAP,
+            join: StrokeOptions::DEFAULT_LINE_JOIN,
+            miter_limit: StrokeOptions::DEFAULT_MITER_LIMIT,
+            dashes: vec![],
+            dash_offset: 0.0,
+        }
+    }
+}
+
+/// An image loaded with `load_image`, drawn with `draw_image`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ImageHandle {
+    texture: TextureId,
+    /// Where the image is in `texture`.
+    uv: Rect,
+    /// Set for layers, whose colors are premultiplied by alpha.
+    premultiplied: bool,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl ImageHandle {
+    /// Vertex color that tints the image with `tint`.
+    fn vertex_color(&self, tint: &Color) -> [f32; 4] {
+        let [r, g, b, a] = tint.to_linear();
+        if self.premultiplied {
+            [r * a, g * a, b * a, a]
+        } else {
+            [r, g, b, a]
+        }
+    }
+}
+
+/// Distances from the edges of an image to the stretchable part of a nine
+/// slice, in image pixels.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Insets {
+    pub left: f32,
+    pub top: f32,
+    pub right: f32,
+    pub bottom: f32,
+}
+impl Insets {
+    pub fn new(left: f32, top: f32, right: f32, bottom: f32) -> Self {
+        Self {
+            left,
+            top,
+            right,
+            bottom,
+        }
+    }
+    pub fn uniform(inset: f32) -> Self {
+        Self::new(inset, inset, inset, inset)
+    }
+}
+
+/// An image divided into a grid of equally sized frames, numbered left to
+/// right and top to bottom.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SpriteSheet {
+    pub image: ImageHandle,
+    pub cols: u32,
+    pub rows: u32,
+}
+impl SpriteSheet {
+    /// Panics if `cols` or `rows` is 0.
+    pub fn from_grid(image: ImageHandle, cols: u32, rows: u32) -> Self {
+        assert!(
+            cols > 0 && rows > 0,
+            "sprite sheet needs at least one frame"
+        );
+        Self { image, cols, rows }
+    }
+    pub fn n_frames(&self) -> usize {
+        (self.cols * self.rows) as usize
+    }
+    pub fn frame_width(&self) -> f32 {
+        self.image.width as f32 / self.cols as f32
+    }
+    pub fn frame_height(&self) -> f32 {
+        self.image.height as f32 / self.rows as f32
+    }
+    /// Part of the image showing `frame`, in image pixels, as taken by
+    /// `draw_image`.
+    pub fn frame_rect(&self, frame: usize) -> Rect {
+        assert!(frame < self.n_frames(), "frame {} out of range", frame);
+        let col = (frame % self.cols as usize) as f32;
+        let row = (frame / self.cols as usize) as f32;
+        let (width, height) = (self.frame_width(), self.frame_height());
+        Rect::new(
+            col * width,
+            row * height,
+            (col + 1.0) * width,
+            (row + 1.0) * height,
+        )
+    }
+}
+
+/// Plays frames of a `SpriteSheet` at a fixed rate, drawn with
+/// `draw_animation`.
+#[derive(Clone, Debug)]
+pub struct Animation {
+    sheet: SpriteSheet,
+    frames: Vec<usize>,
+    frame_duration: f32,
+    /// Whether to start over after the last frame instead of stopping on it.
+    pub looping: bool,
+    elapsed: f32,
+}
+impl Animation {
+    /// Loops through `frames` at `fps` frames per second. Panics if
+    /// `frames` is empty or not all in `sheet`, or if `fps` isn't positive.
+    pub fn new(sheet: SpriteSheet, frames: &[usize], fps: f32) -> Self {
+        assert!(!frames.is_empty(), "animation needs at least one frame");
+        assert!(
+            frames.iter().all(|&frame| frame < sheet.n_frames()),
+            "animation frame out of range of a sheet with {} frames",
+            sheet.n_frames()
+        );
+        assert!(fps > 0.0, "animation fps must be positive");
+        Self {
+            sheet,
+            frames: frames.to_vec(),
+            frame_duration: 1.0 / fps,
+            looping: true,
+            elapsed: 0.0,
+        }
+    }
+    /// Plays every frame of `sheet` in order.
+    pub fn from_sheet(sheet: SpriteSheet, fps: f32) -> Self {
+        Self::new(sheet, &(0..sheet.n_frames()).collect::<Vec<_>>(), fps)
+    }
+    pub fn sheet(&self) -> &SpriteSheet {
+        &self.sheet
+    }
+    /// Frames of the sheet, in the order they are played.
+    pub fn frames(&self) -> &[usize] {
+        &self.frames
+    }
+    /// Seconds each frame is shown for.
+    pub fn frame_duration(&self) -> f32 {
+        self.frame_duration
+    }
+    /// Advances the animation by `dt` seconds.
+    pub fn update(&mut self, dt: f32) {
+        self.elapsed += dt;
+        let duration = self.duration();
+        if self.looping && duration > 0.0 {
+            self.elapsed %= duration;
+        }
+    }
+    pub fn reset(&mut self) {
+        self.elapsed = 0.0;
+    }
+    /// Seconds into the animation.
+    pub fn elapsed(&self) -> f32 {
+        self.elapsed
+    }
+    /// Seconds for one pass through the frames.
+    pub fn duration(&self) -> f32 {
+        self.frames.len() as f32 * self.frame_duration
+    }
+    /// True once a non-looping animation has reached the end of its last
+    /// frame.
+    pub fn is_finished(&self) -> bool {
+        !self.looping && self.elapsed >= self.duration()
+    }
+    /// Index into `frames` of the frame being shown.
+    pub fn frame_index(&self) -> usize {
+        let index = (self.elapsed / self.frame_duration) as usize;
+        index.min(self.frames.len() - 1)
+    }
+    /// Frame of the sheet being shown.
+    pub fn current_frame(&self) -> usize {
+        self.frames[self.frame_index()]
+    }
+}
+
+/// A grid of tiles drawn from a tileset, drawn with `draw_tile_map`. The
+/// geometry of each `TILE_CHUNK_SIZE` x `TILE_CHUNK_SIZE` chunk of tiles is
+/// built once and kept on the GPU, and rebuilt only when one of its tiles
+/// changes. Chunks outside of the frame are not drawn.
+pub struct TileMap {
+    tileset: SpriteSheet,
+    tile_width: f32,
+    tile_height: f32,
+    width: u32,
+    height: u32,
+    /// Tileset frame of each tile, row by row. `None` is an empty tile.
+    tiles: Vec<Option<usize>>,
+    chunks: Vec<TileChunk>,
+    /// `Graphics::dropped_geometry` of the `Graphics` that drew the map,
+    /// which the chunk geometry goes to when the map is dropped.
+    dropped_geometry: Option<sync::Arc<Mutex<Vec<GeometryId>>>>,
+}
+impl TileMap {
+    /// Creates a map of `width` x `height` empty tiles, each drawn
+    /// `tile_width` x `tile_height` large. The chunk geometry that drawing
+    /// the map creates is released by `free_tile_map` or by dropping the
+    /// map.
+    pub fn new(
+        tileset: SpriteSheet,
+        width: u32,
+        height: u32,
+        tile_width: f32,
+        tile_height: f32,
+    ) -> Self {
+        let n_chunks =
+            width.div_ceil(TILE_CHUNK_SIZE) as usize * height.div_ceil(TILE_CHUNK_SIZE) as usize;
+        Self {
+            tileset,
+            tile_width,
+            tile_height,
+            width,
+            height,
+            tiles: vec![None; width as usize * height as usize],
+            chunks: (0..n_chunks)
+                .map(|_| TileChunk {
+                    geometry: None,
+                    dirty: true,
+                })
+                .collect(),
+            dropped_geometry: None,
+        }
+    }
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+    pub fn tileset(&self) -> &SpriteSheet {
+        &self.tileset
+    }
+    /// Panics if a tile of the map is not a frame of `tileset`.
+    pub fn set_tileset(&mut self, tileset: SpriteSheet) {
+        assert!(
+            self.tiles
+                .iter()
+                .flatten()
+                .all(|&tile| tile < tileset.n_frames()),
+            "tile out of range of a tileset with {} frames",
+            tileset.n_frames()
+        );
+        self.tileset = tileset;
+        self.mark_dirty();
+    }
+    /// Size of a tile when drawn.
+    pub fn tile_width(&self) -> f32 {
+        self.tile_width
+    }
+    pub fn tile_height(&self) -> f32 {
+        self.tile_height
+    }
+    pub fn set_tile_size(&mut self, tile_width: f32, tile_height: f32) {
+        self.tile_width = tile_width;
+        self.tile_height = tile_height;
+        self.mark_dirty();
+    }
+    pub fn tile(&self, x: u32, y: u32) -> Option<usize> {
+        assert!(x < self.width && y < self.height, "tile out of range");
+        self.tiles[self.tile_index(x, y)]
+    }
+    /// Panics if `tile` is not a frame of the tileset.
+    pub fn set_tile(&mut self, x: u32, y: u32, tile: Option<usize>) {
+        assert!(x < self.width && y < self.height, "tile out of range");
+        assert!(
+            tile.is_none_or(|tile| tile < self.tileset.n_frames()),
+            "tile out of range of a tileset with {} frames",
+            self.tileset.n_frames()
+        );
+        let index = self.tile_index(x, y);
+        if self.tiles[index] != tile {
+            self.tiles[index] = tile;
+            let chunk = self.chunk_index(x / TILE_CHUNK_SIZE, y / TILE_CHUNK_SIZE);
+            self.chunks[chunk].dirty = true;
+        }
+    }
+    fn tile_index(&self, x: u32, y: u32) -> usize {
+        y as usize * self.width as usize + x as usize
+    }
+    fn chunk_index(&self, chunk_x: u32, chunk_y: u32) -> usize {
+        chunk_y as usize * self.width.div_ceil(TILE_CHUNK_SIZE) as usize + chunk_x as usize
+    }
+    fn mark_dirty(&mut self) {
+        for chunk in &mut self.chunks {
+            chunk.dirty = true;
+        }
+    }
+    /// Tessellates the tiles of a chunk.
+    fn chunk_geometry(&self, chunk_x: u32, chunk_y: u32) -> VertexBuffers<Vertex, u32> {
+        let mut geometry = VertexBuffers::new();
+        let color = self.tileset.image.vertex_color(&Color::rgb(1.0, 1.0, 1.0));
+        let x_end = ((chunk_x + 1) * TILE_CHUNK_SIZE).min(self.width);
+        let y_end = ((chunk_y + 1) * TILE_CHUNK_SIZE).min(self.height);
+        for y in chunk_y * TILE_CHUNK_SIZE..y_end {
+            for x in chunk_x * TILE_CHUNK_SIZE..x_end {
+                if let Some(tile) = self.tiles[self.tile_index(x, y)] {
+                    let x1 = x as f32 * self.tile_width;
+                    let y1 = y as f32 * self.tile_height;
+                    let dst = Rect::new(x1, y1, x1 + self.tile_width, y1 + self.tile_height);
+                    let src = self.tileset.frame_rect(tile);
+                    push_image_quad(&mut geometry, &self.tileset.image, &src, &dst, color);
+                }
+            }
+        }
+        geometry
+    }
+}
+impl Drop for TileMap {
+    fn drop(&mut self) {
+        if let Some(dropped_geometry) = &self.dropped_geometry {
+            let mut dropped_geometry = dropped_geometry.lock().unwrap();
+            dropped_geometry.extend(self.chunks.iter().filter_map(|chunk| chunk.geometry));
+        }
+    }
+}
+
+struct TileChunk {
+    /// `None` if the chunk has no tiles.
+    geometry: Option<GeometryId>,
+    /// Set when the tiles changed since `geometry` was built.
+    dirty: bool,
+}
+
+/// One instance of a `SpriteBatch`.
+#[derive(Clone, Copy, Debug)]
+pub struct Sprite {
+    /// Center of the sprite.
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+    /// Clockwise rotation about the center, in radians.
+    pub rotation: f32,
+    /// Where the sprite is in the batch's texture, such as the `uv` of a
+    /// `TextureRegion`.
+    pub uv: Rect,
+    /// Multiplied with the texture's colors.
+    pub color: Color,
+}
+impl Sprite {
+    pub fn new(x: f32, y: f32, width: f32, height: f32, uv: Rect) -> Self {
+        Self {
+            x,
+            y,
+            width,
+            height,
+            rotation: 0.0,
+            uv,
+            color: Color::rgb(1.0, 1.0, 1.0),
+        }
+    }
+}
+
+/// Sprites sampling the same texture, drawn with `draw_sprites` as one
+/// instanced draw call.
+pub struct SpriteBatch {
+    pub texture: TextureId,
+    pub sprites: Vec<Sprite>,
+}
+impl SpriteBatch {
+    pub fn new(texture: TextureId) -> Self {
+        Self {
+            texture,
+            sprites: vec![],
+        }
+    }
+    pub fn push(&mut self, sprite: Sprite) {
+        self.sprites.push(sprite);
+    }
+    pub fn clear(&mut self) {
+        self.sprites.clear();
+    }
+    pub fn len(&self) -> usize {
+        self.sprites.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.sprites.is_empty()
+    }
+}
+
+/// Per-instance data of a sprite, already transformed. The unit quad's
+/// corners are mapped to `center + axis_x * x + axis_y * y`. Static geometry
+/// is drawn as one instance whose axes and center hold the transform.
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+struct SpriteInstance {
+    axis_x: [f32; 2],
+    axis_y: [f32; 2],
+    center: [f32; 2],
+    uv: [f32; 4],
+    color: [f32; 4],
+}
+
+/// Where data added with `add_texture` is in the atlas: the page to draw
+/// with `add_geom_with_texture` or a `SpriteBatch`, and the UVs within it.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TextureRegion {
+    pub texture: TextureId,
+    pub uv: Rect,
+}
+
+/// A texture that geometry can be drawn with, as returned by
+/// `register_texture_view`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct TextureId(pub(crate) usize);
+
+/// How drawn colors are combined with the colors already in the frame.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum BlendMode {
+    /// Paints over the frame according to alpha.
+    #[default]
+    Normal,
+    /// Adds the color, weighted by alpha. Only ever brightens.
+    Additive,
+    /// Multiplies the frame by the color. Only ever darkens.
+    Multiply,
+    /// Multiplies the inverses of the frame and the color. Only ever
+    /// brightens, less harshly than `Additive`.
+    Screen,
+    /// Writes the color and alpha as is, without blending.
+    Replace,
+}
+impl BlendMode {
+    /// Fragment entry point and blend state of the mode, for colors that are
+    /// `premultiplied` by alpha or not. Multiply and screen only work on
+    /// premultiplied colors, so the shader premultiplies straight ones.
+    fn blend_state(self, premultiplied: bool) -> (&'static str, Option<wgpu::BlendState>) {
+        let blend = |src_factor, dst_factor| wgpu::BlendComponent {
+            src_factor,
+            dst_factor,
+            operation: wgpu::BlendOperation::Add,
+        };
+        let with_color = |color| {
+            Some(wgpu::BlendState {
+                color,
+                alpha: wgpu::BlendComponent::OVER,
+            })
+        };
+        let premultiply = if premultiplied {
+            "fs_main"
+        } else {
+            "fs_premultiplied"
+        };
+        use wgpu::BlendFactor::*;
+        match (self, premultiplied) {
+            (BlendMode::Normal, false) => {
+                ("fs_main", with_color(blend(SrcAlpha, OneMinusSrcAlpha)))
+            }
+            (BlendMode::Normal, true) => ("fs_main", with_color(blend(One, OneMinusSrcAlpha))),
+            (BlendMode::Additive, false) => ("fs_main", with_color(blend(SrcAlpha, One))),
+            (BlendMode::Additive, true) => ("fs_main", with_color(blend(One, One))),
+            (BlendMode::Multiply, _) => (premultiply, with_color(blend(Dst, OneMinusSrcAlpha))),
+            (BlendMode::Screen, _) => (premultiply, with_color(blend(One, OneMinusSrc))),
+            (BlendMode::Replace, _) => ("fs_main", None),
+        }
+    }
+}
+
+/// GPU state that has to change between draw calls. Consecutive `add_geom`
+/// calls with the same state are merged into one batch.
+#[derive(Clone, Copy, PartialEq)]
+struct DrawState {
+    texture: TextureId,
+    pipeline: usize,
+    /// Scissor rect in physical pixels as (x, y, width, height).
+    clip: Option<[u32; 4]>,
+    /// Number of clip paths on the clip stack. Geometry is only drawn where
+    /// the stencil buffer equals this.
+    stencil_ref: u32,
+    /// Batches are drawn in order of z-index, then in the order they were
+    /// added.
+    z_index: i32,
+    /// Index into the frame's gradients, 0 for solid colors.
+    gradient: u32,
+    blend: BlendMode,
+    /// Whether the drawn colors are premultiplied by alpha, as in layers.
+    premultiplied: bool,
+    material: Option<MaterialId>,
+}
+impl DrawState {
+    fn pipeline_key(&self) -> PipelineKey {
+        // clip pipelines write no color
+        if matches!(self.pipeline, PIPELINE_CLIP_PUSH | PIPELINE_CLIP_POP) {
+            return PipelineKey {
+                kind: self.pipeline,
+                blend: BlendMode::Normal,
+                premultiplied: false,
+                material: None,
+            };
+        }
+        PipelineKey {
+            kind: self.pipeline,
+            blend: self.blend,
+            premultiplied: self.premultiplied,
+            material: self.material,
+        }
+    }
+}
+
+/// Identifies a pipeline in `Graphics::pipelines`.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+struct PipelineKey {
+    /// One of the `PIPELINE_*` kinds.
+    kind: usize,
+    blend: BlendMode,
+    premultiplied: bool,
+    material: Option<MaterialId>,
+}
+
+/// Geometry kept on the GPU across frames, as returned by
+/// `create_static_geom`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct GeometryId(usize);
+
+struct StaticGeometry {
+    vertex_buf: wgpu::Buffer,
+    index_buf: wgpu::Buffer,
+    n_indices: u32,
+}
+
+/// A fragment shader registered with `register_material`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct MaterialId(usize);
+
+struct Material {
+    shader: wgpu::ShaderModule,
+    uniform_buf: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+}
+
+enum Clip {
+    /// Already intersected with the enclosing clip rects.
+    Rect(Rect),
+    /// Fill geometry of the path, drawn again on pop to undo its stencil.
+    /// Drawn once for each z-index used while the clip is active, since the
+    /// batches of each z-index are drawn separately.
+    Path {
+        geometry: VertexBuffers<Vertex, u32>,
+        z_indices: Vec<i32>,
+    },
+}
+
+struct Batch {
+    state: DrawState,
+    indices: Range<u32>,
+    /// Range of `Graphics::instances` for sprite batches, drawn over the
+    /// unit quad instead of `indices`.
+    instances: Option<Range<u32>>,
+    /// Static geometry that `indices` refer to instead of the frame's
+    /// geometry, transformed by its instance.
+    geometry: Option<GeometryId>,
+}
+
+/// An offscreen target drawn into between `begin_layer` and `end_layer`.
+struct Layer {
+    /// `None` once `keep_layer` has handed the texture to `image_textures`.
+    texture: Option<wgpu::Texture>,
+    texture_id: TextureId,
+    view: wgpu::TextureView,
+    msaa_view: Option<wgpu::TextureView>,
+    stencil_view: wgpu::TextureView,
+    width: u32,
+    height: u32,
+    /// Logical size, for the vertex shader.
+    size: [f32; 2],
+    batches: Vec<Batch>,
+    /// Whether `blur` is applied after drawing the batches.
+    blurred: bool,
+    /// Created by the first `blur_layer` and kept while the layer is pooled.
+    blur: Option<LayerBlur>,
+}
+
+/// Bind groups of the horizontal pass, from the layer into `view`, and the
+/// vertical pass back, with their uniforms.
+struct LayerBlur {
+    view: wgpu::TextureView,
+    uniform_bufs: [wgpu::Buffer; 2],
+    bind_groups: [wgpu::BindGroup; 2],
+}
+
+/// Parameters of a blur pass, as in `blur.wgsl`.
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+struct BlurUniform {
+    step: [f32; 2],
+    sigma: f32,
+    radius: i32,
+}
+
+/// A layer being drawn, with the drawing state of its parent to restore at
+/// `end_layer`.
+struct OpenLayer {
+    layer: Layer,
+    image: ImageHandle,
+    batches: Vec<Batch>,
+    clip_stack: Vec<Clip>,
+    transform: Transform,
+    transform_stack: Vec<Transform>,
+    draw_state: DrawState,
+}
+
+/// Attachments of a render pass.
+struct PassTarget<'a> {
+    view: &'a wgpu::TextureView,
+    resolve_target: Option<&'a wgpu::TextureView>,
+    stencil_view: &'a wgpu::TextureView,
+    width: u32,
+    height: u32,
+    /// Keeps the content of `view` if `None`.
+    clear_color: Option<Color>,
+    /// Pipelines matching the sample count of `view`.
+    pipelines: &'a HashMap<PipelineKey, wgpu::RenderPipeline>,
 }
 
 pub struct Graphics {
@@ -61,71 +860,178 @@ pub struct Graphics {
     pub scale: f32,
     vertices: Vec<Vertex>,
     indices: Vec<u32>,
-    n_committed_indices: u32,
+    batches: Vec<Batch>,
+    committed_batches: Vec<Batch>,
+    /// Layers begun and not yet ended, innermost last.
+    open_layers: Vec<OpenLayer>,
+    /// Layers ended this frame, in the order they have to be rendered.
+    layers: Vec<Layer>,
+    committed_layers: Vec<Layer>,
+    /// Layers of the previous frame that were not kept, by physical size,
+    /// for `begin_layer` to reuse. The ones not reused are dropped.
+    layer_pool: HashMap<(u32, u32), Vec<Layer>>,
+    draw_state: DrawState,
+    clip_stack: Vec<Clip>,
+    transform: Transform,
+    transform_stack: Vec<Transform>,
     pub config: wgpu::SurfaceConfiguration,
     pub device: wgpu::Device,
     pub queue: wgpu::Queue,
     vertex_buf: wgpu::Buffer,
     index_buf: wgpu::Buffer,
+    /// Sprite instances of the frame being built.
+    instances: Vec<SpriteInstance>,
+    instance_buf: wgpu::Buffer,
+    /// The two triangles of a unit quad centered on the origin, which
+    /// sprites are instanced from.
+    quad_buf: wgpu::Buffer,
+    static_geometry: HashMap<GeometryId, StaticGeometry>,
+    next_geometry_id: usize,
+    /// Static geometry freed in a frame, with the frame's number. It is
+    /// kept until the batches of that frame have been replaced.
+    retired_geometry: Vec<(GeometryId, u64)>,
+    /// Chunk geometry of dropped tile maps, retired by `commit_geom`.
+    dropped_geometry: sync::Arc<Mutex<Vec<GeometryId>>>,
+    /// Logical size of the frame, followed by the sizes of the frame's
+    /// layers, `uniform_stride` apart.
     uniform_buf: wgpu::Buffer,
-    texture: wgpu::Texture,
-    bind_group: wgpu::BindGroup,
-    pipeline: wgpu::RenderPipeline,
-    texture_cur_x: u32,
-    texture_cur_y: u32,
-    texture_cur_max_height: u32,
+    uniform_stride: u64,
+    bind_group_layout: wgpu::BindGroupLayout,
+    sampler: wgpu::Sampler,
+    bind_groups: HashMap<TextureId, wgpu::BindGroup>,
+    next_texture_id: usize,
+    gradient_bind_group_layout: wgpu::BindGroupLayout,
+    gradient_buf: wgpu::Buffer,
+    gradient_bind_group: wgpu::BindGroup,
+    /// Size of a gradient in `gradient_buf`, padded to the uniform offset
+    /// alignment.
+    gradient_stride: u64,
+    /// Gradients of the frame being built. The first one is all zeros and
+    /// used by solid colors.
+    gradients: Vec<GradientUniform>,
+    pipeline_layout: wgpu::PipelineLayout,
+    shader: wgpu::ShaderModule,
+    material_bind_group_layout: wgpu::BindGroupLayout,
+    /// Like `pipeline_layout`, plus the material uniforms at group 2.
+    material_pipeline_layout: wgpu::PipelineLayout,
+    materials: Vec<Material>,
+    blur_bind_group_layout: wgpu::BindGroupLayout,
+    blur_pipeline: wgpu::RenderPipeline,
+    /// Created when first needed by `ensure_pipeline`.
+    pipelines: HashMap<PipelineKey, wgpu::RenderPipeline>,
+    /// Pipelines without msaa, for frames drawn over the target's content
+    /// while msaa is enabled.
+    single_sample_pipelines: HashMap<PipelineKey, wgpu::RenderPipeline>,
+    /// Stencil attachment of those frames, created when first needed.
+    single_sample_stencil_view: Option<wgpu::TextureView>,
+    msaa_samples: u32,
+    msaa_view: Option<wgpu::TextureView>,
+    clear_color: Option<Color>,
+    stencil_view: wgpu::TextureView,
+    atlas_pages: Vec<AtlasPage>,
+    glyph_pages: Vec<AtlasPage>,
+    /// Textures of images too large for the atlas.
+    image_textures: HashMap<TextureId, wgpu::Texture>,
+    /// Glyph pages replaced by a repack, kept alive until the batches that
+    /// still sample them have been replaced by the next `commit_geom`.
+    retired_pages: Vec<AtlasPage>,
+    /// Images freed in a frame, with the frame's number. Like
+    /// `retired_geometry`, they are kept until that frame has been drawn.
+    retired_images: Vec<(TextureId, u64)>,
     font: Font,
-    font_atlas: HashMap<GlyphRasterConfig, Rect>,
+    /// Rasterized glyphs in the glyph pages.
+    font_atlas: HashMap<GlyphRasterConfig, AtlasEntry>,
+    frame: u64,
+    /// Number of frames a glyph can go unused before it is evicted from the
+    /// atlas.
+    pub glyph_max_age: u64,
 }
 
-const MAX_N_VERTICES: usize = 100000;
-const MAX_N_INDICES: usize = 100000;
+const INITIAL_N_VERTICES: usize = 16384;
+const INITIAL_N_INDICES: usize = 16384;
+const INITIAL_N_INSTANCES: usize = 1024;
+/// Most dashes and gaps `dash_path` splits a path into. Patterns that would
+/// need more are drawn as a solid line instead.
+const MAX_DASHES: f64 = (1 << 20) as f64;
+/// Width and height of the chunks of a `TileMap`, in tiles.
+pub const TILE_CHUNK_SIZE: u32 = 16;
+const INITIAL_N_GRADIENTS: u64 = 64;
+/// Room in `uniform_buf` for the frame and this many layers, less one.
+const INITIAL_N_TARGETS: u64 = 64;
+// gradient kinds, see `GradientUniform` and `gradient_kind_constants`
+const GRADIENT_LINEAR: u32 = 1;
+const GRADIENT_RADIAL: u32 = 2;
+const GRADIENT_CONIC: u32 = 3;
+/// `params` holds the rect of the shadow.
+const GRADIENT_BOX_SHADOW: u32 = 4;
+/// Most texels sampled on each side of a texel by a blur pass.
+const MAX_BLUR_RADIUS: i32 = 64;
 const TEXTURE_SIZE: u32 = 1000;
+const DEFAULT_GLYPH_MAX_AGE: u64 = 600;
+pub(crate) const STENCIL_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Stencil8;
+
+// pipeline kinds, see `PipelineKey`
+const PIPELINE_DEFAULT: usize = 0;
+const PIPELINE_CLIP_PUSH: usize = 1;
+const PIPELINE_CLIP_POP: usize = 2;
+const PIPELINE_SPRITES: usize = 3;
+const PIPELINE_STATIC: usize = 4;
+const HEADLESS_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8UnormSrgb;
 
 impl Graphics {
+    /// Draws to textures of `config.view_formats[0]`, or of `config.format`
+    /// if there are no view formats, which should be an sRGB format for
+    /// colors to be blended in linear light and shown as intended, see
+    /// `Color`.
     pub fn init(
         config: wgpu::SurfaceConfiguration,
         device: wgpu::Device,
         queue: wgpu::Queue,
     ) -> Self {
-        let vertices = [Vertex {
-            pos: [0.0, 0.0],
-            uv: [0.0, 0.0],
-            color: [0.0, 0.0, 0.0, 0.0],
-        }; MAX_N_VERTICES];
-        let vertex_buf = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            label: None,
-            contents: bytemuck::cast_slice(&vertices),
-            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
-        });
-        let indices = [0u32; MAX_N_INDICES];
-        let index_buf = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            label: None,
-            contents: bytemuck::cast_slice(&indices),
-            usage: wgpu::BufferUsages::INDEX | wgpu::BufferUsages::COPY_DST,
-        });
+        let vertex_buf = create_geom_buffer(
+            &device,
+            (INITIAL_N_VERTICES * mem::size_of::<Vertex>()) as wgpu::BufferAddress,
+            wgpu::BufferUsages::VERTEX,
+        );
+        let index_buf = create_geom_buffer(
+            &device,
+            (INITIAL_N_INDICES * mem::size_of::<u32>()) as wgpu::BufferAddress,
+            wgpu::BufferUsages::INDEX,
+        );
+        let instance_buf = create_geom_buffer(
+            &device,
+            (INITIAL_N_INSTANCES * mem::size_of::<SpriteInstance>()) as wgpu::BufferAddress,
+            wgpu::BufferUsages::VERTEX,
+        );
+        let quad: [[f32; 2]; 6] = [
+            [-0.5, -0.5],
+            [-0.5, 0.5],
+            [0.5, 0.5],
+            [-0.5, -0.5],
+            [0.5, 0.5],
+            [0.5, -0.5],
+        ];
+        let quad_buf = create_geom_buffer(
+            &device,
+            mem::size_of_val(&quad) as wgpu::BufferAddress,
+            wgpu::BufferUsages::VERTEX,
+        );
+        queue.write_buffer(&quad_buf, 0, bytemuck::cast_slice(&quad));
+        let alignment = device.limits().min_uniform_buffer_offset_alignment as u64;
+        let uniform_stride = (mem::size_of::<[f32; 2]>() as u64).div_ceil(alignment) * alignment;
+        let uniform_buf = create_geom_buffer(
+            &device,
+            uniform_stride * INITIAL_N_TARGETS,
+            wgpu::BufferUsages::UNIFORM,
+        );
         let size = [config.width as f32, config.height as f32];
-        let uniform_buf = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            label: None,
-            contents: bytemuck::cast_slice(&size),
-            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
-        });
+        queue.write_buffer(&uniform_buf, 0, bytemuck::cast_slice(&size));
 
         let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
             label: None,
             entries: &[
                 wgpu::BindGroupLayoutEntry {
                     binding: 0,
-                    visibility: wgpu::ShaderStages::VERTEX,
-                    ty: wgpu::BindingType::Buffer {
-                        ty: wgpu::BufferBindingType::Uniform,
-                        has_dynamic_offset: false,
-                        min_binding_size: wgpu::BufferSize::new(8),
-                    },
-                    count: None,
-                },
-                wgpu::BindGroupLayoutEntry {
-                    binding: 1,
                     visibility: wgpu::ShaderStages::FRAGMENT,
                     ty: wgpu::BindingType::Texture {
                         multisampled: false,
@@ -135,7 +1041,7 @@ impl Graphics {
                     count: None,
                 },
                 wgpu::BindGroupLayoutEntry {
-                    binding: 2,
+                    binding: 1,
                     visibility: wgpu::ShaderStages::FRAGMENT,
                     ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
                     count: None,
@@ -143,62 +1049,145 @@ impl Graphics {
             ],
         });
 
+        let gradient_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: None,
+                entries: &[
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: true,
+                            min_binding_size: wgpu::BufferSize::new(
+                                mem::size_of::<GradientUniform>() as u64,
+                            ),
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: true,
+                            min_binding_size: wgpu::BufferSize::new(8),
+                        },
+                        count: None,
+                    },
+                ],
+            });
+        let gradient_stride =
+            (mem::size_of::<GradientUniform>() as u64).div_ceil(alignment) * alignment;
+        let gradient_buf = create_geom_buffer(
+            &device,
+            INITIAL_N_GRADIENTS * gradient_stride,
+            wgpu::BufferUsages::UNIFORM,
+        );
+        let gradient_bind_group = create_gradient_bind_group(
+            &device,
+            &gradient_bind_group_layout,
+            &gradient_buf,
+            &uniform_buf,
+        );
+
         let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
             label: None,
-            bind_group_layouts: &[&bind_group_layout],
+            bind_group_layouts: &[&bind_group_layout, &gradient_bind_group_layout],
             push_constant_ranges: &[],
         });
 
         let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
             label: None,
-            source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(include_str!("shader.wgsl"))),
+            source: wgpu::ShaderSource::Wgsl(Cow::Owned(format!(
+                "{}{}{}",
+                gradient_kind_constants(),
+                include_str!("common.wgsl"),
+                include_str!("shader.wgsl")
+            ))),
         });
 
-        let vertex_bufs = [wgpu::VertexBufferLayout {
-            array_stride: mem::size_of::<Vertex>() as wgpu::BufferAddress,
-            step_mode: wgpu::VertexStepMode::Vertex,
-            attributes: &[
-                wgpu::VertexAttribute {
-                    format: wgpu::VertexFormat::Float32x2,
-                    offset: 0,
-                    shader_location: 0,
-                },
-                wgpu::VertexAttribute {
-                    format: wgpu::VertexFormat::Float32x2,
-                    offset: 4 * 2,
-                    shader_location: 1,
-                },
-                wgpu::VertexAttribute {
-                    format: wgpu::VertexFormat::Float32x4,
-                    offset: 4 * 4,
-                    shader_location: 2,
-                },
-            ],
-        }];
-
-        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-            label: None,
-            layout: Some(&pipeline_layout),
-            vertex: wgpu::VertexState {
-                module: &shader,
-                entry_point: "vs_main",
-                buffers: &vertex_bufs,
+        let material_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: None,
+                entries: &[wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                }],
+            });
+        let material_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: None,
+                bind_group_layouts: &[
+                    &bind_group_layout,
+                    &gradient_bind_group_layout,
+                    &material_bind_group_layout,
+                ],
+                push_constant_ranges: &[],
+            });
+
+        let blur_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: None,
+                entries: &[
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: wgpu::BufferSize::new(
+                                mem::size_of::<BlurUniform>() as u64
+                            ),
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Texture {
+                            multisampled: false,
+                            sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                            view_dimension: wgpu::TextureViewDimension::D2,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 2,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                        count: None,
+                    },
+                ],
+            });
+        let blur_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: None,
+            source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(include_str!("blur.wgsl"))),
+        });
+        let blur_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: None,
+            layout: Some(
+                &device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                    label: None,
+                    bind_group_layouts: &[&blur_bind_group_layout],
+                    push_constant_ranges: &[],
+                }),
+            ),
+            vertex: wgpu::VertexState {
+                module: &blur_shader,
+                entry_point: "vs_main",
+                buffers: &[],
             },
             fragment: Some(wgpu::FragmentState {
-                module: &shader,
+                module: &blur_shader,
                 entry_point: "fs_main",
-                targets: &[Some(wgpu::ColorTargetState {
-                    format: config.view_formats[0].into(),
-                    blend: Some(wgpu::BlendState {
-                        color: wgpu::BlendComponent {
-                            src_factor: wgpu::BlendFactor::SrcAlpha,
-                            dst_factor: wgpu::BlendFactor::OneMinusSrcAlpha,
-                            operation: wgpu::BlendOperation::Add,
-                        },
-                        alpha: wgpu::BlendComponent::OVER,
-                    }),
-                    write_mask: wgpu::ColorWrites::ALL,
-                })],
+                targets: &[Some(view_format(&config).into())],
             }),
             primitive: wgpu::PrimitiveState::default(),
             depth_stencil: None,
@@ -206,42 +1195,9 @@ impl Graphics {
             multiview: None,
         });
 
-        let texture_extent = wgpu::Extent3d {
-            width: TEXTURE_SIZE,
-            height: TEXTURE_SIZE,
-            depth_or_array_layers: 1,
-        };
-        let texture = device.create_texture(&wgpu::TextureDescriptor {
-            label: None,
-            size: texture_extent,
-            mip_level_count: 1,
-            sample_count: 1,
-            dimension: wgpu::TextureDimension::D2,
-            format: wgpu::TextureFormat::Rgba8UnormSrgb,
-            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
-            view_formats: &[],
-        });
-        let texture_view = texture.create_view(&wgpu::TextureViewDescriptor::default());
-
+        let stencil_view =
+            create_render_target(&device, STENCIL_FORMAT, config.width, config.height, 1);
         let sampler = device.create_sampler(&wgpu::SamplerDescriptor::default());
-        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            layout: &bind_group_layout,
-            entries: &[
-                wgpu::BindGroupEntry {
-                    binding: 0,
-                    resource: uniform_buf.as_entire_binding(),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 1,
-                    resource: wgpu::BindingResource::TextureView(&texture_view),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 2,
-                    resource: wgpu::BindingResource::Sampler(&sampler),
-                },
-            ],
-            label: None,
-        });
 
         let font = Font::from_bytes(
             include_bytes!("../resources/WorkSans-Light.ttf") as &[u8],
@@ -249,28 +1205,223 @@ impl Graphics {
         )
         .unwrap();
 
-        Self {
+        let mut gfx = Self {
             width: 0.0,
             height: 0.0,
             scale: 0.0,
             indices: vec![],
             vertices: vec![],
-            n_committed_indices: 0,
+            batches: vec![],
+            committed_batches: vec![],
+            open_layers: vec![],
+            layers: vec![],
+            committed_layers: vec![],
+            layer_pool: HashMap::new(),
+            draw_state: DrawState {
+                // replaced by the texture passed to each add_geom call
+                texture: TextureId(0),
+                pipeline: PIPELINE_DEFAULT,
+                blend: BlendMode::Normal,
+                premultiplied: false,
+                material: None,
+                clip: None,
+                stencil_ref: 0,
+                z_index: 0,
+                gradient: 0,
+            },
+            clip_stack: vec![],
+            transform: Transform::identity(),
+            transform_stack: vec![],
             config,
             device,
             queue,
             vertex_buf,
             index_buf,
+            instances: vec![],
+            instance_buf,
+            quad_buf,
+            static_geometry: HashMap::new(),
+            next_geometry_id: 0,
+            retired_geometry: vec![],
             uniform_buf,
-            texture,
-            bind_group,
-            pipeline,
-            texture_cur_x: 0,
-            texture_cur_y: 0,
-            texture_cur_max_height: 0,
+            uniform_stride,
+            bind_group_layout,
+            sampler,
+            bind_groups: HashMap::new(),
+            next_texture_id: 0,
+            gradient_bind_group_layout,
+            gradient_buf,
+            gradient_bind_group,
+            gradient_stride,
+            gradients: vec![GradientUniform::zeroed()],
+            pipeline_layout,
+            shader,
+            material_bind_group_layout,
+            material_pipeline_layout,
+            materials: vec![],
+            blur_bind_group_layout,
+            blur_pipeline,
+            pipelines: HashMap::new(),
+            single_sample_pipelines: HashMap::new(),
+            single_sample_stencil_view: None,
+            msaa_samples: 1,
+            msaa_view: None,
+            clear_color: Some(Color::rgba(0.0, 0.0, 0.0, 0.0)),
+            stencil_view,
+            atlas_pages: vec![],
+            glyph_pages: vec![],
+            image_textures: HashMap::new(),
+            retired_pages: vec![],
+            retired_images: vec![],
+            dropped_geometry: sync::Arc::new(Mutex::new(vec![])),
             font,
             font_atlas: HashMap::new(),
+            frame: 0,
+            glyph_max_age: DEFAULT_GLYPH_MAX_AGE,
+        };
+
+        for kind in [
+            PIPELINE_DEFAULT,
+            PIPELINE_CLIP_PUSH,
+            PIPELINE_CLIP_POP,
+            PIPELINE_SPRITES,
+            PIPELINE_STATIC,
+        ] {
+            gfx.ensure_pipeline(PipelineKey {
+                kind,
+                blend: BlendMode::Normal,
+                premultiplied: false,
+                material: None,
+            });
         }
+        let page = gfx.create_atlas_page(TEXTURE_SIZE, TEXTURE_SIZE);
+        gfx.atlas_pages.push(page);
+
+        // add blank texture at uv coords (0, 0)
+        gfx.add_texture(&[0xff; 4], 1, 1);
+
+        gfx
+    }
+
+    /// Creates a `Graphics` that renders offscreen instead of to a window
+    /// surface. Falls back to a software adapter if no hardware one is found.
+    pub fn headless(width: u32, height: u32, scale: f32) -> Self {
+        pollster::block_on(Self::headless_async(width, height, scale))
+    }
+
+    async fn headless_async(width: u32, height: u32, scale: f32) -> Self {
+        let instance = wgpu::Instance::default();
+        let adapter = match instance
+            .request_adapter(&wgpu::RequestAdapterOptions::default())
+            .await
+        {
+            Some(adapter) => adapter,
+            None => instance
+                .request_adapter(&wgpu::RequestAdapterOptions {
+                    force_fallback_adapter: true,
+                    ..Default::default()
+                })
+                .await
+                .expect("no wgpu adapter available"),
+        };
+
+        let (device, queue) = adapter
+            .request_device(&wgpu::DeviceDescriptor::default(), None)
+            .await
+            .unwrap();
+
+        let config = wgpu::SurfaceConfiguration {
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+            format: HEADLESS_FORMAT,
+            width,
+            height,
+            present_mode: wgpu::PresentMode::Fifo,
+            alpha_mode: wgpu::CompositeAlphaMode::Auto,
+            view_formats: vec![HEADLESS_FORMAT],
+        };
+
+        let mut gfx = Self::init(config, device, queue);
+        gfx.resize(width, height, scale);
+        gfx
+    }
+
+    /// Renders the committed geometry into an offscreen texture and reads it
+    /// back as tightly packed RGBA rows of `config.width` x `config.height`.
+    pub fn render_to_image(&mut self) -> Vec<u8> {
+        let width = self.config.width;
+        let height = self.config.height;
+        let texture = self.device.create_texture(&wgpu::TextureDescriptor {
+            label: None,
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: self.config.format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        self.render(&view);
+
+        // rows in a texture-to-buffer copy must be padded to 256 bytes
+        let row_bytes = width * 4;
+        let align = wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+        let padded_row_bytes = row_bytes.div_ceil(align) * align;
+        let readback_buf = self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: None,
+            size: (padded_row_bytes * height) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        encoder.copy_texture_to_buffer(
+            wgpu::ImageCopyTexture {
+                texture: &texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All,
+            },
+            wgpu::ImageCopyBuffer {
+                buffer: &readback_buf,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: Some(padded_row_bytes),
+                    rows_per_image: None,
+                },
+            },
+            wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+        );
+        self.queue.submit(Some(encoder.finish()));
+
+        let slice = readback_buf.slice(..);
+        slice.map_async(wgpu::MapMode::Read, |result| result.unwrap());
+        self.device.poll(wgpu::Maintain::Wait);
+
+        let mut pixels = Vec::with_capacity((row_bytes * height) as usize);
+        {
+            let data = slice.get_mapped_range();
+            for row in data.chunks(padded_row_bytes as usize) {
+                pixels.extend_from_slice(&row[..row_bytes as usize]);
+            }
+        }
+        readback_buf.unmap();
+        pixels
+    }
+
+    /// Format of the views of the surface textures that are drawn to.
+    pub fn view_format(&self) -> wgpu::TextureFormat {
+        view_format(&self.config)
     }
 
     pub fn resize(&mut self, width: u32, height: u32, scale: f32) {
@@ -279,14 +1430,462 @@ impl Graphics {
         self.width = width as f32 / scale;
         self.height = height as f32 / scale;
         self.scale = scale;
+        let resized = (width, height) != (self.config.width, self.config.height);
         self.config.width = width;
         self.config.height = height;
+        if resized {
+            self.create_targets();
+        }
         let size = [self.width, self.height];
         self.queue
             .write_buffer(&self.uniform_buf, 0, bytemuck::cast_slice(&size));
     }
 
+    /// Sets the number of samples per pixel used to anti-alias geometry.
+    /// Must be 1, 2, 4 or 8 and supported by the adapter for the surface
+    /// format and `STENCIL_FORMAT`; 1 and 4 are always supported.
+    pub fn set_msaa_samples(&mut self, samples: u32) {
+        assert!(
+            matches!(samples, 1 | 2 | 4 | 8),
+            "msaa sample count must be 1, 2, 4 or 8"
+        );
+        if samples == self.msaa_samples {
+            return;
+        }
+        self.msaa_samples = samples;
+        self.recreate_pipelines();
+        self.create_targets();
+        // pooled layers have attachments of the old sample count
+        self.recycle_layers(vec![]);
+    }
+
+    pub fn msaa_samples(&self) -> u32 {
+        self.msaa_samples
+    }
+
+    /// Sets the color `render` clears the frame to, transparent black by
+    /// default. With `None` the frame is drawn over the content of the
+    /// target instead, without msaa.
+    pub fn set_clear_color(&mut self, color: Option<Color>) {
+        self.clear_color = color;
+    }
+
+    pub fn clear_color(&self) -> Option<Color> {
+        self.clear_color
+    }
+
+    /// Sets how everything drawn from now on is blended into the frame.
+    pub fn set_blend_mode(&mut self, mode: BlendMode) {
+        self.draw_state.blend = mode;
+        self.ensure_pipeline(self.draw_state.pipeline_key());
+    }
+
+    pub fn blend_mode(&self) -> BlendMode {
+        self.draw_state.blend
+    }
+
+    /// Recreates the render targets that have to match the size and sample
+    /// count of the frame.
+    fn create_targets(&mut self) {
+        let (width, height) = (self.config.width, self.config.height);
+        self.stencil_view = create_render_target(
+            &self.device,
+            STENCIL_FORMAT,
+            width,
+            height,
+            self.msaa_samples,
+        );
+        self.msaa_view = (self.msaa_samples > 1).then(|| {
+            create_render_target(
+                &self.device,
+                self.view_format(),
+                width,
+                height,
+                self.msaa_samples,
+            )
+        });
+        self.single_sample_stencil_view = None;
+    }
+
+    fn ensure_pipeline(&mut self, key: PipelineKey) {
+        if !self.pipelines.contains_key(&key) {
+            let pipeline = self.create_pipeline(key, self.msaa_samples);
+            self.pipelines.insert(key, pipeline);
+        }
+    }
+
+    /// Recreates the cached pipelines after a change to the render targets.
+    fn recreate_pipelines(&mut self) {
+        let keys: Vec<_> = self.pipelines.keys().copied().collect();
+        for key in keys {
+            let pipeline = self.create_pipeline(key, self.msaa_samples);
+            self.pipelines.insert(key, pipeline);
+        }
+    }
+
+    /// Render pipeline for `Vertex` geometry, or for sprite instances. Every
+    /// pipeline only draws where the stencil buffer equals the batch's clip
+    /// depth. Clip pipelines change the stencil there instead of drawing.
+    fn create_pipeline(&self, key: PipelineKey, samples: u32) -> wgpu::RenderPipeline {
+        let (write_mask, stencil_op) = match key.kind {
+            PIPELINE_CLIP_PUSH => (
+                wgpu::ColorWrites::empty(),
+                wgpu::StencilOperation::IncrementClamp,
+            ),
+            PIPELINE_CLIP_POP => (
+                wgpu::ColorWrites::empty(),
+                wgpu::StencilOperation::DecrementClamp,
+            ),
+            _ => (wgpu::ColorWrites::ALL, wgpu::StencilOperation::Keep),
+        };
+        let (mut fragment_entry_point, blend) = key.blend.blend_state(key.premultiplied);
+        let (layout, fragment_shader) = match key.material {
+            Some(material) => {
+                fragment_entry_point = "fs_main";
+                (
+                    &self.material_pipeline_layout,
+                    &self.materials[material.0].shader,
+                )
+            }
+            None => (&self.pipeline_layout, &self.shader),
+        };
+        let vertex_bufs = [wgpu::VertexBufferLayout {
+            array_stride: mem::size_of::<Vertex>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Vertex,
+            attributes: &[
+                wgpu::VertexAttribute {
+                    format: wgpu::VertexFormat::Float32x2,
+                    offset: 0,
+                    shader_location: 0,
+                },
+                wgpu::VertexAttribute {
+                    format: wgpu::VertexFormat::Float32x2,
+                    offset: 4 * 2,
+                    shader_location: 1,
+                },
+                wgpu::VertexAttribute {
+                    format: wgpu::VertexFormat::Float32x4,
+                    offset: 4 * 4,
+                    shader_location: 2,
+                },
+            ],
+        }];
+        let sprite_bufs = [
+            wgpu::VertexBufferLayout {
+                array_stride: mem::size_of::<[f32; 2]>() as wgpu::BufferAddress,
+                step_mode: wgpu::VertexStepMode::Vertex,
+                attributes: &wgpu::vertex_attr_array![0 => Float32x2],
+            },
+            wgpu::VertexBufferLayout {
+                array_stride: mem::size_of::<SpriteInstance>() as wgpu::BufferAddress,
+                step_mode: wgpu::VertexStepMode::Instance,
+                attributes: &wgpu::vertex_attr_array![
+                    1 => Float32x2,
+                    2 => Float32x2,
+                    3 => Float32x2,
+                    4 => Float32x4,
+                    5 => Float32x4,
+                ],
+            },
+        ];
+        let static_bufs = [
+            vertex_bufs[0].clone(),
+            wgpu::VertexBufferLayout {
+                array_stride: mem::size_of::<SpriteInstance>() as wgpu::BufferAddress,
+                step_mode: wgpu::VertexStepMode::Instance,
+                attributes: &wgpu::vertex_attr_array![
+                    3 => Float32x2,
+                    4 => Float32x2,
+                    5 => Float32x2,
+                ],
+            },
+        ];
+        let (vertex_entry_point, buffers): (_, &[_]) = match key.kind {
+            PIPELINE_SPRITES => ("vs_sprite", &sprite_bufs),
+            PIPELINE_STATIC => ("vs_static", &static_bufs),
+            _ => ("vs_main", &vertex_bufs),
+        };
+
+        let stencil_face = wgpu::StencilFaceState {
+            compare: wgpu::CompareFunction::Equal,
+            fail_op: wgpu::StencilOperation::Keep,
+            depth_fail_op: wgpu::StencilOperation::Keep,
+            pass_op: stencil_op,
+        };
+
+        self.device
+            .create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: None,
+                layout: Some(layout),
+                vertex: wgpu::VertexState {
+                    module: &self.shader,
+                    entry_point: vertex_entry_point,
+                    buffers,
+                },
+                fragment: Some(wgpu::FragmentState {
+                    module: fragment_shader,
+                    entry_point: fragment_entry_point,
+                    targets: &[Some(wgpu::ColorTargetState {
+                        format: self.view_format(),
+                        blend,
+                        write_mask,
+                    })],
+                }),
+                primitive: wgpu::PrimitiveState::default(),
+                depth_stencil: Some(wgpu::DepthStencilState {
+                    format: STENCIL_FORMAT,
+                    depth_write_enabled: false,
+                    depth_compare: wgpu::CompareFunction::Always,
+                    stencil: wgpu::StencilState {
+                        front: stencil_face,
+                        back: stencil_face,
+                        read_mask: !0,
+                        write_mask: !0,
+                    },
+                    bias: wgpu::DepthBiasState::default(),
+                }),
+                multisample: wgpu::MultisampleState {
+                    count: samples,
+                    ..Default::default()
+                },
+                multiview: None,
+            })
+    }
+
+    /// Makes a texture view available to `add_geom_with_texture`. The view is
+    /// sampled with the same sampler as the built-in atlas.
+    pub fn register_texture_view(&mut self, view: &wgpu::TextureView) -> TextureId {
+        let bind_group = self.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &self.bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&self.sampler),
+                },
+            ],
+            label: None,
+        });
+        let texture_id = TextureId(self.next_texture_id);
+        self.next_texture_id += 1;
+        self.bind_groups.insert(texture_id, bind_group);
+        texture_id
+    }
+
+    /// Releases a texture registered with `register_texture_view`. It must
+    /// not be drawn with afterwards.
+    pub fn unregister_texture(&mut self, texture: TextureId) {
+        self.bind_groups.remove(&texture);
+    }
+
+    /// Adds untextured geometry, which samples the blank texel at uv (0, 0)
+    /// of the first atlas page. Geometry using UVs from `add_texture` has to
+    /// be added with `add_geom_with_texture` and the region's texture, as
+    /// the region may be on another page. Vertex colors are linear, see
+    /// `Vertex::color`.
     pub fn add_geom(&mut self, vertices: &[Vertex], indices: &[u32]) {
+        self.add_geom_with_texture(vertices, indices, self.atlas_page(0));
+    }
+
+    /// Adds geometry sampling `texture`. Vertex positions are mapped through
+    /// the current transform.
+    pub fn add_geom_with_texture(
+        &mut self,
+        vertices: &[Vertex],
+        indices: &[u32],
+        texture: TextureId,
+    ) {
+        if self.transform == Transform::identity() {
+            self.push_geom(vertices, indices, texture);
+        } else {
+            let mut vertices = vertices.to_vec();
+            self.transform_vertices(&mut vertices);
+            self.push_geom(&vertices, indices, texture);
+        }
+    }
+
+    /// Uploads geometry to be drawn with `draw_static_geom` in any number of
+    /// frames, without being uploaded again by `commit_geom`. It stays on the
+    /// GPU until released with `free_static_geom`.
+    pub fn create_static_geom(&mut self, vertices: &[Vertex], indices: &[u32]) -> GeometryId {
+        // buffers are written in multiples of 4 bytes and can't be empty
+        let vertex_buf = create_geom_buffer(
+            &self.device,
+            mem::size_of_val(vertices).max(4) as wgpu::BufferAddress,
+            wgpu::BufferUsages::VERTEX,
+        );
+        let index_buf = create_geom_buffer(
+            &self.device,
+            mem::size_of_val(indices).max(4) as wgpu::BufferAddress,
+            wgpu::BufferUsages::INDEX,
+        );
+        self.queue
+            .write_buffer(&vertex_buf, 0, bytemuck::cast_slice(vertices));
+        self.queue
+            .write_buffer(&index_buf, 0, bytemuck::cast_slice(indices));
+
+        let geometry = GeometryId(self.next_geometry_id);
+        self.next_geometry_id += 1;
+        self.static_geometry.insert(
+            geometry,
+            StaticGeometry {
+                vertex_buf,
+                index_buf,
+                n_indices: indices.len() as u32,
+            },
+        );
+        geometry
+    }
+
+    /// Draws geometry from `create_static_geom` sampling `texture`, mapped
+    /// through the current transform. Panics if the geometry has been freed
+    /// in an earlier frame.
+    pub fn draw_static_geom(&mut self, geometry: GeometryId, texture: TextureId) {
+        if self.clip_rect().is_some_and(|clip| clip.is_empty()) {
+            return;
+        }
+        let n_indices = self
+            .static_geometry
+            .get(&geometry)
+            .expect("draw_static_geom called with freed geometry")
+            .n_indices;
+        if n_indices == 0 {
+            return;
+        }
+
+        let t = self.transform;
+        let instance = self.instances.len() as u32;
+        self.instances.push(SpriteInstance {
+            axis_x: t.transform_vector(vector(1.0, 0.0)).to_array(),
+            axis_y: t.transform_vector(vector(0.0, 1.0)).to_array(),
+            center: t.transform_point(point(0.0, 0.0)).to_array(),
+            uv: [0.0; 4],
+            color: [0.0; 4],
+        });
+        let state = DrawState {
+            texture,
+            pipeline: PIPELINE_STATIC,
+            ..self.draw_state
+        };
+        self.ensure_pipeline(state.pipeline_key());
+        self.batches.push(Batch {
+            state,
+            indices: 0..n_indices,
+            instances: Some(instance..instance + 1),
+            geometry: Some(geometry),
+        });
+    }
+
+    /// Releases geometry from `create_static_geom`. It must not be drawn
+    /// afterwards, but frames that already drew it still render.
+    pub fn free_static_geom(&mut self, geometry: GeometryId) {
+        self.retired_geometry.push((geometry, self.frame));
+    }
+
+    /// Compiles a fragment shader for `add_geom_with_material`. `source` is
+    /// WGSL that can use the declarations in `common.wgsl` and must define
+    /// `@fragment fn fs_main(vertex: VertexOutput) -> @location(0) vec4<f32>`.
+    /// `uniforms` is the initial content of the material's uniform buffer,
+    /// bound at `@group(2) @binding(0)`. Vertex colors and texture samples
+    /// are in linear light. Under the multiply and screen blend modes the
+    /// shader has to premultiply its color by alpha. Returns the validation
+    /// error if the shader does not compile or its pipeline can't be created.
+    pub fn register_material(
+        &mut self,
+        source: &str,
+        uniforms: &[u8],
+    ) -> Result<MaterialId, wgpu::Error> {
+        self.device.push_error_scope(wgpu::ErrorFilter::Validation);
+        let shader = self
+            .device
+            .create_shader_module(wgpu::ShaderModuleDescriptor {
+                label: None,
+                source: wgpu::ShaderSource::Wgsl(Cow::Owned(format!(
+                    "{}\n{}",
+                    include_str!("common.wgsl"),
+                    source
+                ))),
+            });
+        // uniform bindings are sized in multiples of 16 bytes
+        let size = (uniforms.len() as u64).max(1).div_ceil(16) * 16;
+        let uniform_buf = create_geom_buffer(&self.device, size, wgpu::BufferUsages::UNIFORM);
+        let mut data = uniforms.to_vec();
+        data.resize(size as usize, 0);
+        self.queue.write_buffer(&uniform_buf, 0, &data);
+        let bind_group = self.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: None,
+            layout: &self.material_bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: uniform_buf.as_entire_binding(),
+            }],
+        });
+        self.materials.push(Material {
+            shader,
+            uniform_buf,
+            bind_group,
+        });
+        let material = MaterialId(self.materials.len() - 1);
+        // create the pipeline for the default state now, so that errors in
+        // the shader's interface are reported here rather than when drawing
+        let key = PipelineKey {
+            kind: PIPELINE_DEFAULT,
+            blend: BlendMode::Normal,
+            premultiplied: false,
+            material: Some(material),
+        };
+        let pipeline = self.create_pipeline(key, self.msaa_samples);
+        if let Some(error) = pollster::block_on(self.device.pop_error_scope()) {
+            self.materials.pop();
+            return Err(error);
+        }
+        self.pipelines.insert(key, pipeline);
+        Ok(material)
+    }
+
+    /// Replaces the start of a material's uniform buffer with `uniforms`,
+    /// which must be no larger than the data it was registered with. All
+    /// geometry of a frame sees the uniforms as they are at `render`.
+    pub fn set_material_uniforms(&mut self, material: MaterialId, uniforms: &[u8]) {
+        let uniform_buf = &self.materials[material.0].uniform_buf;
+        assert!(
+            uniforms.len() as u64 <= uniform_buf.size(),
+            "material uniforms larger than when registered"
+        );
+        // buffer writes have to be a multiple of 4 bytes
+        let mut data = uniforms.to_vec();
+        data.resize(uniforms.len().div_ceil(4) * 4, 0);
+        self.queue.write_buffer(uniform_buf, 0, &data);
+    }
+
+    /// Adds geometry drawn with a material's shader instead of the built-in
+    /// one. Vertex positions are mapped through the current transform, and
+    /// `tex` samples the first atlas page.
+    pub fn add_geom_with_material(
+        &mut self,
+        vertices: &[Vertex],
+        indices: &[u32],
+        material: MaterialId,
+    ) {
+        self.draw_state.material = Some(material);
+        self.ensure_pipeline(self.draw_state.pipeline_key());
+        self.add_geom(vertices, indices);
+        self.draw_state.material = None;
+    }
+
+    fn push_geom(&mut self, vertices: &[Vertex], indices: &[u32], texture: TextureId) {
+        let is_stencil = matches!(
+            self.draw_state.pipeline,
+            PIPELINE_CLIP_PUSH | PIPELINE_CLIP_POP
+        );
+        if !is_stencil && self.clip_rect().is_some_and(|clip| clip.is_empty()) {
+            return;
+        }
+
+        let start = self.indices.len() as u32;
         self.indices.append(
             &mut indices
                 .iter()
@@ -295,108 +1894,1058 @@ impl Graphics {
                 .to_vec(),
         );
         self.vertices.append(&mut vertices.to_vec());
-        self.n_committed_indices = self.indices.len() as u32;
+        let end = self.indices.len() as u32;
+
+        let state = DrawState {
+            texture,
+            ..self.draw_state
+        };
+        match self.batches.last_mut() {
+            // a layer may have added indices since the last batch
+            Some(batch) if batch.state == state && batch.indices.end == start => {
+                batch.indices.end = end
+            }
+            _ => self.batches.push(Batch {
+                state,
+                indices: start..end,
+                instances: None,
+                geometry: None,
+            }),
+        }
+    }
+
+    fn push_instances(&mut self, instances: &[SpriteInstance], texture: TextureId) {
+        if self.clip_rect().is_some_and(|clip| clip.is_empty()) {
+            return;
+        }
+
+        let start = self.instances.len() as u32;
+        self.instances.extend_from_slice(instances);
+        let end = self.instances.len() as u32;
+
+        let state = DrawState {
+            texture,
+            pipeline: PIPELINE_SPRITES,
+            ..self.draw_state
+        };
+        self.ensure_pipeline(state.pipeline_key());
+        match self.batches.last_mut() {
+            Some(Batch {
+                state: batch_state,
+                instances: Some(range),
+                geometry: None,
+                ..
+            }) if *batch_state == state && range.end == start => range.end = end,
+            _ => self.batches.push(Batch {
+                state,
+                indices: 0..0,
+                instances: Some(start..end),
+                geometry: None,
+            }),
+        }
+    }
+
+    /// Innermost clip rect on the clip stack.
+    fn clip_rect(&self) -> Option<&Rect> {
+        self.clip_stack.iter().rev().find_map(|clip| match clip {
+            Clip::Rect(rect) => Some(rect),
+            Clip::Path { .. } => None,
+        })
+    }
+
+    /// Restricts drawing to `rect` intersected with the current clip, until
+    /// the matching `pop_clip`. Under a rotating or skewing transform the
+    /// rect is clipped as a path instead.
+    pub fn push_clip_rect(&mut self, rect: Rect) {
+        let t = &self.transform;
+        if t.m12 != 0.0 || t.m21 != 0.0 {
+            let mut builder = Path::builder();
+            builder.add_rectangle(
+                &Box2D::new(
+                    Point2D::new(rect.x1, rect.y1),
+                    Point2D::new(rect.x2, rect.y2),
+                ),
+                Winding::Positive,
+            );
+            self.push_clip_path(builder.build());
+            return;
+        }
+        let p1 = t.transform_point(point(rect.x1, rect.y1));
+        let p2 = t.transform_point(point(rect.x2, rect.y2));
+        let rect = Rect::new(
+            p1.x.min(p2.x),
+            p1.y.min(p2.y),
+            p1.x.max(p2.x),
+            p1.y.max(p2.y),
+        );
+
+        let clip = match self.clip_rect() {
+            Some(parent) => parent.intersect(&rect),
+            None => rect,
+        };
+        self.clip_stack.push(Clip::Rect(clip));
+        self.update_clip();
+    }
+
+    /// Restricts drawing to the inside of `path` intersected with the
+    /// current clip, until the matching `pop_clip`.
+    pub fn push_clip_path(&mut self, path: Path) {
+        let options = FillOptions::tolerance(self.tolerance());
+        let mut geometry = fill_geometry(&path, &options, [0.0; 4]);
+        self.transform_vertices(&mut geometry.vertices);
+        self.add_stencil_geom(&geometry, PIPELINE_CLIP_PUSH);
+        self.clip_stack.push(Clip::Path {
+            geometry,
+            z_indices: vec![self.draw_state.z_index],
+        });
+        self.update_clip();
+    }
+
+    /// Removes the innermost clip pushed with `push_clip_rect` or
+    /// `push_clip_path`.
+    pub fn pop_clip(&mut self) {
+        let clip = self
+            .clip_stack
+            .pop()
+            .expect("pop_clip called without a matching push");
+        if let Clip::Path {
+            geometry,
+            z_indices,
+        } = clip
+        {
+            let z_index = self.draw_state.z_index;
+            for z in z_indices {
+                self.draw_state.z_index = z;
+                self.add_stencil_geom(&geometry, PIPELINE_CLIP_POP);
+            }
+            self.draw_state.z_index = z_index;
+        }
+        self.update_clip();
+    }
+
+    /// Adds clip geometry that has already been transformed. It is not
+    /// scissored, so that it cancels out however the clip rect changes
+    /// between push and pop.
+    fn add_stencil_geom(&mut self, geometry: &VertexBuffers<Vertex, u32>, pipeline: usize) {
+        let state = self.draw_state;
+        self.draw_state.pipeline = pipeline;
+        self.draw_state.clip = None;
+        self.push_geom(&geometry.vertices, &geometry.indices, self.atlas_page(0));
+        self.draw_state = state;
+    }
+
+    /// Sets the z-index of everything drawn from now on, 0 by default.
+    /// Geometry with a higher z-index is drawn on top of geometry with a
+    /// lower one, whatever the order it was added in.
+    pub fn set_z_index(&mut self, z_index: i32) {
+        self.draw_state.z_index = z_index;
+
+        // draw the active clip paths into the stencil of this z-index
+        let mut depth = 0;
+        let mut pushes = vec![];
+        for clip in &mut self.clip_stack {
+            if let Clip::Path {
+                geometry,
+                z_indices,
+            } = clip
+            {
+                if !z_indices.contains(&z_index) {
+                    z_indices.push(z_index);
+                    pushes.push((depth, geometry.clone()));
+                }
+                depth += 1;
+            }
+        }
+        for (depth, geometry) in pushes {
+            self.draw_state.stencil_ref = depth;
+            self.add_stencil_geom(&geometry, PIPELINE_CLIP_PUSH);
+        }
+        self.update_clip();
+    }
+
+    pub fn z_index(&self) -> i32 {
+        self.draw_state.z_index
+    }
+
+    /// Saves the current transform, to be restored by `pop_transform`.
+    pub fn push_transform(&mut self) {
+        self.transform_stack.push(self.transform);
+    }
+
+    pub fn pop_transform(&mut self) {
+        self.transform = self
+            .transform_stack
+            .pop()
+            .expect("pop_transform called without a matching push");
+    }
+
+    /// The transform applied to everything drawn from now on.
+    pub fn transform(&self) -> Transform {
+        self.transform
+    }
+
+    /// Applies `transform` before the current transform.
+    pub fn apply_transform(&mut self, transform: &Transform) {
+        self.transform = transform.then(&self.transform);
+    }
+
+    pub fn translate(&mut self, x: f32, y: f32) {
+        self.apply_transform(&Transform::translation(x, y));
+    }
+
+    /// Rotates clockwise by `angle` radians around the current origin.
+    pub fn rotate(&mut self, angle: f32) {
+        self.apply_transform(&Transform::rotation(Angle::radians(angle)));
+    }
+
+    pub fn scale(&mut self, x: f32, y: f32) {
+        self.apply_transform(&Transform::scale(x, y));
+    }
+
+    fn transform_vertices(&self, vertices: &mut [Vertex]) {
+        for vertex in vertices {
+            let pos = self
+                .transform
+                .transform_point(point(vertex.pos[0], vertex.pos[1]));
+            vertex.pos = pos.to_array();
+        }
+    }
+
+    /// Tessellation tolerance in local units that stays at the default
+    /// tolerance once the current transform has scaled the path.
+    fn tolerance(&self) -> f32 {
+        let scale = self.transform.determinant().abs().sqrt();
+        if scale > 0.0 {
+            FillOptions::DEFAULT_TOLERANCE / scale
+        } else {
+            FillOptions::DEFAULT_TOLERANCE
+        }
+    }
+
+    fn update_clip(&mut self) {
+        self.draw_state.stencil_ref = self
+            .clip_stack
+            .iter()
+            .filter(|clip| matches!(clip, Clip::Path { .. }))
+            .count() as u32;
+        self.draw_state.clip = self.clip_rect().map(|clip| {
+            let x1 = (clip.x1 * self.scale).floor().max(0.0) as u32;
+            let y1 = (clip.y1 * self.scale).floor().max(0.0) as u32;
+            let x2 = ((clip.x2 * self.scale).ceil().max(0.0) as u32).max(x1);
+            let y2 = ((clip.y2 * self.scale).ceil().max(0.0) as u32).max(y1);
+            [x1, y1, x2 - x1, y2 - y1]
+        });
     }
 
     pub fn commit_geom(&mut self) {
+        let vertex_bytes = (self.vertices.len() * mem::size_of::<Vertex>()) as wgpu::BufferAddress;
+        if vertex_bytes > self.vertex_buf.size() {
+            self.vertex_buf = create_geom_buffer(
+                &self.device,
+                vertex_bytes.next_power_of_two(),
+                wgpu::BufferUsages::VERTEX,
+            );
+        }
+        let index_bytes = (self.indices.len() * mem::size_of::<u32>()) as wgpu::BufferAddress;
+        if index_bytes > self.index_buf.size() {
+            self.index_buf = create_geom_buffer(
+                &self.device,
+                index_bytes.next_power_of_two(),
+                wgpu::BufferUsages::INDEX,
+            );
+        }
+
         self.queue
             .write_buffer(&self.vertex_buf, 0, bytemuck::cast_slice(&self.vertices));
         self.queue
             .write_buffer(&self.index_buf, 0, bytemuck::cast_slice(&self.indices));
+        let instance_bytes =
+            (self.instances.len() * mem::size_of::<SpriteInstance>()) as wgpu::BufferAddress;
+        if instance_bytes > self.instance_buf.size() {
+            self.instance_buf = create_geom_buffer(
+                &self.device,
+                instance_bytes.next_power_of_two(),
+                wgpu::BufferUsages::VERTEX,
+            );
+        }
+        self.queue
+            .write_buffer(&self.instance_buf, 0, bytemuck::cast_slice(&self.instances));
+        assert!(
+            self.open_layers.is_empty(),
+            "begin_layer called without a matching end_layer"
+        );
+        self.commit_target_sizes();
+        self.commit_gradients();
+        self.committed_batches = mem::take(&mut self.batches);
+        // stable, so batches of the same z-index keep their order
+        self.committed_batches
+            .sort_by_key(|batch| batch.state.z_index);
+        let rendered_layers = mem::replace(&mut self.committed_layers, mem::take(&mut self.layers));
+        self.recycle_layers(rendered_layers);
         self.vertices.clear();
         self.indices.clear();
+        self.instances.clear();
+
+        for page in mem::take(&mut self.retired_pages) {
+            self.unregister_texture(page.texture_id);
+        }
+        let frame = self.frame;
+        for geometry in mem::take(&mut *self.dropped_geometry.lock().unwrap()) {
+            self.retired_geometry.push((geometry, frame));
+        }
+        let static_geometry = &mut self.static_geometry;
+        self.retired_geometry.retain(|&(geometry, retired_frame)| {
+            let keep = retired_frame == frame;
+            if !keep {
+                static_geometry.remove(&geometry);
+            }
+            keep
+        });
+        for (texture, retired_frame) in mem::take(&mut self.retired_images) {
+            if retired_frame == frame {
+                self.retired_images.push((texture, retired_frame));
+            } else {
+                self.image_textures.remove(&texture);
+                self.unregister_texture(texture);
+            }
+        }
+        self.frame += 1;
+        self.evict_glyphs();
+    }
+
+    /// Replaces the layer pool with the unkept layers of a frame that has been
+    /// rendered, releasing the pooled layers that were not reused.
+    fn recycle_layers(&mut self, layers: Vec<Layer>) {
+        for (_, pooled) in mem::take(&mut self.layer_pool) {
+            for layer in pooled {
+                self.unregister_texture(layer.texture_id);
+            }
+        }
+        for mut layer in layers {
+            if layer.texture.is_some() {
+                layer.batches.clear();
+                self.layer_pool
+                    .entry((layer.width, layer.height))
+                    .or_default()
+                    .push(layer);
+            }
+        }
+    }
+
+    /// Writes the sizes of the frame's layers after the frame's own, growing
+    /// `uniform_buf` if needed.
+    fn commit_target_sizes(&mut self) {
+        let size_bytes = (self.layers.len() as u64 + 1) * self.uniform_stride;
+        if size_bytes > self.uniform_buf.size() {
+            self.uniform_buf = create_geom_buffer(
+                &self.device,
+                size_bytes.next_power_of_two(),
+                wgpu::BufferUsages::UNIFORM,
+            );
+            let size = [self.width, self.height];
+            self.queue
+                .write_buffer(&self.uniform_buf, 0, bytemuck::cast_slice(&size));
+            self.gradient_bind_group = create_gradient_bind_group(
+                &self.device,
+                &self.gradient_bind_group_layout,
+                &self.gradient_buf,
+                &self.uniform_buf,
+            );
+        }
+        for (i, layer) in self.layers.iter().enumerate() {
+            self.queue.write_buffer(
+                &self.uniform_buf,
+                (i as u64 + 1) * self.uniform_stride,
+                bytemuck::cast_slice(&layer.size),
+            );
+        }
+    }
+
+    fn commit_gradients(&mut self) {
+        let stride = self.gradient_stride as usize;
+        let gradient_bytes = (self.gradients.len() * stride) as wgpu::BufferAddress;
+        if gradient_bytes > self.gradient_buf.size() {
+            self.gradient_buf = create_geom_buffer(
+                &self.device,
+                gradient_bytes.next_power_of_two(),
+                wgpu::BufferUsages::UNIFORM,
+            );
+            self.gradient_bind_group = create_gradient_bind_group(
+                &self.device,
+                &self.gradient_bind_group_layout,
+                &self.gradient_buf,
+                &self.uniform_buf,
+            );
+        }
+
+        let mut data = vec![0u8; gradient_bytes as usize];
+        for (chunk, gradient) in data.chunks_mut(stride).zip(&self.gradients) {
+            chunk[..mem::size_of::<GradientUniform>()]
+                .copy_from_slice(bytemuck::bytes_of(gradient));
+        }
+        self.queue.write_buffer(&self.gradient_buf, 0, &data);
+        self.gradients.truncate(1);
+    }
+
+    /// Makes `paint` the paint of the geometry added until `end_paint`, and
+    /// returns the vertex color to draw it with.
+    fn begin_paint(&mut self, paint: &Paint) -> [f32; 4] {
+        let (kind, params, stops) = match paint {
+            Paint::Solid(color) => {
+                self.draw_state.gradient = 0;
+                return color.to_linear();
+            }
+            Paint::LinearGradient {
+                x1,
+                y1,
+                x2,
+                y2,
+                stops,
+            } => (GRADIENT_LINEAR, [*x1, *y1, *x2, *y2], stops),
+            Paint::RadialGradient {
+                x,
+                y,
+                radius,
+                stops,
+            } => (GRADIENT_RADIAL, [*x, *y, *radius, 0.0], stops),
+            Paint::ConicGradient { x, y, angle, stops } => {
+                (GRADIENT_CONIC, [*x, *y, *angle, 0.0], stops)
+            }
+        };
+
+        assert!(!stops.is_empty(), "gradient needs at least one stop");
+        let mut stops = stops.clone();
+        stops.sort_by(|a, b| a.offset.total_cmp(&b.offset));
+        stops.truncate(MAX_GRADIENT_STOPS);
+        let mut gradient = GradientUniform::zeroed();
+        gradient.kind = kind;
+        gradient.n_stops = stops.len() as u32;
+        gradient.params = params;
+        for (i, stop) in stops.iter().enumerate() {
+            let color = stop.color;
+            gradient.offsets[i / 4][i % 4] = stop.offset;
+            gradient.colors[i] = color.to_linear();
+        }
+        self.use_gradient(gradient);
+        [1.0; 4]
+    }
+
+    /// Makes `gradient` apply to the geometry added until `end_paint`, in
+    /// the current coordinate space.
+    fn use_gradient(&mut self, mut gradient: GradientUniform) {
+        let inverse = self.transform.inverse().unwrap_or_else(Transform::identity);
+        gradient.inverse_transform = [
+            [inverse.m11, inverse.m21, inverse.m31, 0.0],
+            [inverse.m12, inverse.m22, inverse.m32, 0.0],
+        ];
+        self.gradients.push(gradient);
+        self.draw_state.gradient = (self.gradients.len() - 1) as u32;
+    }
+
+    fn end_paint(&mut self) {
+        self.draw_state.gradient = 0;
+    }
+
+    /// Texture of an atlas page. The first one holds the blank texel of
+    /// untextured geometry.
+    pub fn atlas_page(&self, page: usize) -> TextureId {
+        self.atlas_pages[page].texture_id
+    }
+
+    fn create_atlas_page(&mut self, width: u32, height: u32) -> AtlasPage {
+        let texture = self.device.create_texture(&wgpu::TextureDescriptor {
+            label: None,
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Rgba8UnormSrgb,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING
+                | wgpu::TextureUsages::COPY_DST
+                | wgpu::TextureUsages::COPY_SRC,
+            view_formats: &[],
+        });
+        let texture_view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let texture_id = self.register_texture_view(&texture_view);
+        AtlasPage::new(texture, texture_id, width, height)
+    }
+
+    /// Finds room in `pages`, adding a page if none has space. Pages are at
+    /// least `TEXTURE_SIZE`, so larger textures get a page of their own.
+    fn alloc_in_pages(&mut self, glyph: bool, width: u32, height: u32) -> (usize, u32, u32) {
+        let pages = if glyph {
+            &mut self.glyph_pages
+        } else {
+            &mut self.atlas_pages
+        };
+        for (page, atlas_page) in pages.iter_mut().enumerate().rev() {
+            if let Some((x, y)) = atlas_page.space.alloc(width, height) {
+                return (page, x, y);
+            }
+        }
+
+        let mut atlas_page =
+            self.create_atlas_page(width.max(TEXTURE_SIZE), height.max(TEXTURE_SIZE));
+        let (x, y) = atlas_page.space.alloc(width, height).unwrap();
+        let pages = if glyph {
+            &mut self.glyph_pages
+        } else {
+            &mut self.atlas_pages
+        };
+        pages.push(atlas_page);
+        (pages.len() - 1, x, y)
+    }
+
+    fn write_atlas_texture(
+        &self,
+        texture: &wgpu::Texture,
+        x: u32,
+        y: u32,
+        data: &[u8],
+        width: u32,
+        height: u32,
+    ) {
+        self.queue.write_texture(
+            wgpu::ImageCopyTexture {
+                texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d { x, y, z: 0 },
+                aspect: wgpu::TextureAspect::All,
+            },
+            data,
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(width * 4),
+                rows_per_image: None,
+            },
+            wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+        );
+    }
+
+    /// Copies RGBA `data` into the atlas and returns where it is. A new page
+    /// is started when the existing ones are full. Space used by
+    /// `add_texture` is never reclaimed.
+    pub fn add_texture(&mut self, data: &[u8], width: u32, height: u32) -> TextureRegion {
+        let (page, x, y) = self.alloc_in_pages(false, width, height);
+        let atlas_page = &self.atlas_pages[page];
+        self.write_atlas_texture(&atlas_page.texture, x, y, data, width, height);
+        TextureRegion {
+            texture: atlas_page.texture_id,
+            uv: atlas_page.uv_rect(x, y, width, height),
+        }
+    }
+
+    /// Decodes a PNG or JPEG image and uploads it. Images up to half the
+    /// atlas page size are added to the atlas, larger ones get a texture of
+    /// their own. Images with no pixels or larger than the device supports
+    /// are rejected.
+    pub fn load_image(&mut self, bytes: &[u8]) -> image::ImageResult<ImageHandle> {
+        let image = image::load_from_memory(bytes)?.into_rgba8();
+        let (width, height) = image.dimensions();
+        let max_size = self.device.limits().max_texture_dimension_2d;
+        if width == 0 || height == 0 || width > max_size || height > max_size {
+            return Err(image::ImageError::Limits(
+                image::error::LimitError::from_kind(image::error::LimitErrorKind::DimensionError),
+            ));
+        }
+
+        if width > TEXTURE_SIZE / 2 || height > TEXTURE_SIZE / 2 {
+            let page = self.create_atlas_page(width, height);
+            self.write_atlas_texture(&page.texture, 0, 0, &image, width, height);
+            self.image_textures.insert(page.texture_id, page.texture);
+            return Ok(ImageHandle {
+                texture: page.texture_id,
+                uv: Rect::new(0.0, 0.0, 1.0, 1.0),
+                premultiplied: false,
+                width,
+                height,
+            });
+        }
+
+        // surround the image with a copy of its edge pixels, so that
+        // filtering at the edges does not pick up its atlas neighbours
+        let padded_width = width + 2;
+        let mut padded = Vec::with_capacity((padded_width * (height + 2) * 4) as usize);
+        for y in 0..height + 2 {
+            let y = y.saturating_sub(1).min(height - 1);
+            for x in 0..padded_width {
+                let x = x.saturating_sub(1).min(width - 1);
+                padded.extend_from_slice(&image.get_pixel(x, y).0);
+            }
+        }
+        let region = self.add_texture(&padded, padded_width, height + 2);
+        let page = self
+            .atlas_pages
+            .iter()
+            .find(|page| page.texture_id == region.texture)
+            .unwrap();
+        let (du, dv) = (
+            1.0 / page.space.width as f32,
+            1.0 / page.space.height as f32,
+        );
+        let rect = region.uv;
+        Ok(ImageHandle {
+            texture: region.texture,
+            uv: Rect::new(rect.x1 + du, rect.y1 + dv, rect.x2 - du, rect.y2 - dv),
+            premultiplied: false,
+            width,
+            height,
+        })
+    }
+
+    /// Releases an image that has a texture of its own, such as a layer.
+    /// Images in the atlas keep their space. Frames that already drew the
+    /// image still render.
+    pub fn free_image(&mut self, image: ImageHandle) {
+        if self.image_textures.contains_key(&image.texture)
+            && !self.retired_images.iter().any(|&(t, _)| t == image.texture)
+        {
+            self.retired_images.push((image.texture, self.frame));
+        }
+    }
+
+    /// Drops glyphs that have not been drawn for `glyph_max_age` frames and
+    /// repacks glyph pages that have become fragmented.
+    fn evict_glyphs(&mut self) {
+        let mut pages: Vec<_> = self
+            .glyph_pages
+            .iter_mut()
+            .map(|page| &mut page.space)
+            .collect();
+        evict_stale(
+            &mut self.font_atlas,
+            &mut pages,
+            self.frame,
+            self.glyph_max_age,
+        );
+
+        for page in 0..self.glyph_pages.len() {
+            if self.glyph_pages[page].space.is_fragmented() {
+                self.repack_glyph_page(page);
+            }
+        }
+    }
+
+    /// Moves the live glyphs of a page into a fresh page at the same index.
+    /// Glyphs that no longer fit are dropped and rasterized again when next
+    /// drawn.
+    fn repack_glyph_page(&mut self, page: usize) {
+        let space = &self.glyph_pages[page].space;
+        let mut new_page = self.create_atlas_page(space.width, space.height);
+        let old_texture = &self.glyph_pages[page].texture;
+
+        let mut entries: Vec<_> = self
+            .font_atlas
+            .iter_mut()
+            .filter(|(_, entry)| entry.page == page)
+            .collect();
+        entries.sort_by_key(|(_, entry)| std::cmp::Reverse(entry.height));
+
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        let mut dropped = vec![];
+        for (key, entry) in entries {
+            let Some((x, y)) = new_page.space.alloc(entry.width, entry.height) else {
+                dropped.push(*key);
+                continue;
+            };
+            if entry.width > 0 && entry.height > 0 {
+                encoder.copy_texture_to_texture(
+                    wgpu::ImageCopyTexture {
+                        texture: old_texture,
+                        mip_level: 0,
+                        origin: wgpu::Origin3d {
+                            x: entry.x,
+                            y: entry.y,
+                            z: 0,
+                        },
+                        aspect: wgpu::TextureAspect::All,
+                    },
+                    wgpu::ImageCopyTexture {
+                        texture: &new_page.texture,
+                        mip_level: 0,
+                        origin: wgpu::Origin3d { x, y, z: 0 },
+                        aspect: wgpu::TextureAspect::All,
+                    },
+                    wgpu::Extent3d {
+                        width: entry.width,
+                        height: entry.height,
+                        depth_or_array_layers: 1,
+                    },
+                );
+            }
+            entry.x = x;
+            entry.y = y;
+        }
+        self.queue.submit(Some(encoder.finish()));
+
+        for key in dropped {
+            self.font_atlas.remove(&key);
+        }
+        let old_page = mem::replace(&mut self.glyph_pages[page], new_page);
+        self.retired_pages.push(old_page);
+    }
+
+    pub fn render(&mut self, view: &wgpu::TextureView) {
+        if self.clear_color.is_none() && self.msaa_samples > 1 {
+            self.prepare_single_sample_frame();
+        }
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        for (i, layer) in self.committed_layers.iter().enumerate() {
+            let target = PassTarget {
+                view: layer.msaa_view.as_ref().unwrap_or(&layer.view),
+                resolve_target: layer.msaa_view.as_ref().map(|_| &layer.view),
+                stencil_view: &layer.stencil_view,
+                width: layer.width,
+                height: layer.height,
+                clear_color: Some(Color::rgba(0.0, 0.0, 0.0, 0.0)),
+                pipelines: &self.pipelines,
+            };
+            let size_offset = (i as u64 + 1) * self.uniform_stride;
+            self.draw_pass(&mut encoder, target, &layer.batches, size_offset as u32);
+            if let Some(blur) = layer.blur.as_ref().filter(|_| layer.blurred) {
+                self.blur_pass(&mut encoder, &blur.view, &blur.bind_groups[0]);
+                self.blur_pass(&mut encoder, &layer.view, &blur.bind_groups[1]);
+            }
+        }
+        let target = if self.clear_color.is_none() && self.msaa_samples > 1 {
+            PassTarget {
+                view,
+                resolve_target: None,
+                stencil_view: self.single_sample_stencil_view.as_ref().unwrap(),
+                width: self.config.width,
+                height: self.config.height,
+                clear_color: None,
+                pipelines: &self.single_sample_pipelines,
+            }
+        } else {
+            PassTarget {
+                view: self.msaa_view.as_ref().unwrap_or(view),
+                resolve_target: self.msaa_view.as_ref().map(|_| view),
+                stencil_view: &self.stencil_view,
+                width: self.config.width,
+                height: self.config.height,
+                clear_color: self.clear_color,
+                pipelines: &self.pipelines,
+            }
+        };
+        self.draw_pass(&mut encoder, target, &self.committed_batches, 0);
+        self.queue.submit(Some(encoder.finish()));
+    }
+
+    /// Creates what drawing the frame without msaa needs. A multisampled
+    /// attachment can't be loaded with the content of the target, so frames
+    /// drawn over it are not anti-aliased.
+    fn prepare_single_sample_frame(&mut self) {
+        for batch in &self.committed_batches {
+            let key = batch.state.pipeline_key();
+            if !self.single_sample_pipelines.contains_key(&key) {
+                let pipeline = self.create_pipeline(key, 1);
+                self.single_sample_pipelines.insert(key, pipeline);
+            }
+        }
+        if self.single_sample_stencil_view.is_none() {
+            self.single_sample_stencil_view = Some(create_render_target(
+                &self.device,
+                STENCIL_FORMAT,
+                self.config.width,
+                self.config.height,
+                1,
+            ));
+        }
+    }
+
+    /// Clears `target`, if it has a clear color, and draws `batches` into
+    /// it. `size_offset` selects the target's size in `uniform_buf`.
+    fn draw_pass(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        target: PassTarget,
+        batches: &[Batch],
+        size_offset: u32,
+    ) {
+        let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: None,
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: target.view,
+                resolve_target: target.resolve_target,
+                ops: wgpu::Operations {
+                    load: match target.clear_color {
+                        Some(color) => {
+                            let [r, g, b, a] = color.to_linear();
+                            wgpu::LoadOp::Clear(wgpu::Color {
+                                r: r as f64,
+                                g: g as f64,
+                                b: b as f64,
+                                a: a as f64,
+                            })
+                        }
+                        None => wgpu::LoadOp::Load,
+                    },
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                view: target.stencil_view,
+                depth_ops: None,
+                stencil_ops: Some(wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(0),
+                    store: wgpu::StoreOp::Discard,
+                }),
+            }),
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+        if !batches.is_empty() {
+            rpass.set_vertex_buffer(1, self.instance_buf.slice(..));
+        }
+        for batch in batches {
+            // the target may have been resized since the clip was pushed
+            let [x, y, w, h] = batch.state.clip.unwrap_or([0, 0, u32::MAX, u32::MAX]);
+            let x = x.min(target.width);
+            let y = y.min(target.height);
+            let w = w.min(target.width - x);
+            let h = h.min(target.height - y);
+            if w == 0 || h == 0 {
+                continue;
+            }
+            rpass.set_scissor_rect(x, y, w, h);
+            rpass.set_stencil_reference(batch.state.stencil_ref);
+            rpass.set_pipeline(&target.pipelines[&batch.state.pipeline_key()]);
+            rpass.set_bind_group(0, &self.bind_groups[&batch.state.texture], &[]);
+            let gradient_offset = batch.state.gradient as u64 * self.gradient_stride;
+            rpass.set_bind_group(
+                1,
+                &self.gradient_bind_group,
+                &[gradient_offset as u32, size_offset],
+            );
+            if let Some(material) = batch.state.material {
+                rpass.set_bind_group(2, &self.materials[material.0].bind_group, &[]);
+            }
+            match (&batch.geometry, &batch.instances) {
+                (Some(geometry), Some(instances)) => {
+                    let geometry = &self.static_geometry[geometry];
+                    rpass.set_vertex_buffer(0, geometry.vertex_buf.slice(..));
+                    rpass.set_index_buffer(geometry.index_buf.slice(..), wgpu::IndexFormat::Uint32);
+                    rpass.draw_indexed(batch.indices.clone(), 0, instances.clone());
+                }
+                (_, Some(instances)) => {
+                    rpass.set_vertex_buffer(0, self.quad_buf.slice(..));
+                    rpass.draw(0..6, instances.clone());
+                }
+                _ => {
+                    rpass.set_vertex_buffer(0, self.vertex_buf.slice(..));
+                    rpass.set_index_buffer(self.index_buf.slice(..), wgpu::IndexFormat::Uint32);
+                    rpass.draw_indexed(batch.indices.clone(), 0, 0..1);
+                }
+            }
+        }
+    }
+
+    /// Draws the texture of `bind_group` blurred in one direction into
+    /// `view`.
+    fn blur_pass(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        view: &wgpu::TextureView,
+        bind_group: &wgpu::BindGroup,
+    ) {
+        let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: None,
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+        rpass.set_pipeline(&self.blur_pipeline);
+        rpass.set_bind_group(0, bind_group, &[]);
+        rpass.draw(0..3, 0..1);
+    }
+
+    /// Blurs the content of the innermost layer with a gaussian of standard
+    /// deviation `sigma`, once it has been drawn. A `sigma` of 0 turns the
+    /// blur off again. `sigma` is limited to a third of `MAX_BLUR_RADIUS`
+    /// physical pixels, which bounds the samples per pixel.
+    pub fn blur_layer(&mut self, sigma: f32) {
+        let open = self
+            .open_layers
+            .last_mut()
+            .expect("blur_layer called outside of a layer");
+        open.layer.blurred = sigma > 0.0;
+        if sigma <= 0.0 {
+            return;
+        }
+
+        let (width, height) = (open.layer.width, open.layer.height);
+        if open.layer.blur.is_none() {
+            let blur = create_layer_blur(
+                &self.device,
+                &self.blur_bind_group_layout,
+                &self.sampler,
+                view_format(&self.config),
+                &open.layer,
+            );
+            open.layer.blur = Some(blur);
+        }
+        let blur = open.layer.blur.as_ref().unwrap();
+
+        // the gaussian is negligible past 3 standard deviations
+        let sigma = (sigma * self.scale).min(MAX_BLUR_RADIUS as f32 / 3.0);
+        let radius = ((3.0 * sigma).ceil() as i32).min(MAX_BLUR_RADIUS);
+        let steps = [[1.0 / width as f32, 0.0], [0.0, 1.0 / height as f32]];
+        for (uniform_buf, step) in blur.uniform_bufs.iter().zip(steps) {
+            let uniform = BlurUniform {
+                step,
+                sigma,
+                radius,
+            };
+            self.queue
+                .write_buffer(uniform_buf, 0, bytemuck::bytes_of(&uniform));
+        }
+    }
+
+    /// Redirects drawing into a transparent `width` x `height` texture
+    /// until the matching `end_layer`, for example to fade a group of
+    /// overlapping shapes as one. Layers start without clip or transform
+    /// and can be nested.
+    ///
+    /// The layer's image must only be drawn in the frame it was ended in.
+    /// After that its texture goes back to a pool and is reused by a later
+    /// layer of the same size or released, unless it is passed to
+    /// `keep_layer`, which makes the caller responsible for releasing it
+    /// with `free_image`.
+    pub fn begin_layer(&mut self, width: f32, height: f32) {
+        let physical_width = ((width * self.scale).ceil() as u32).max(1);
+        let physical_height = ((height * self.scale).ceil() as u32).max(1);
+        let pooled = self
+            .layer_pool
+            .get_mut(&(physical_width, physical_height))
+            .and_then(|pooled| pooled.pop());
+        let mut layer = match pooled {
+            Some(layer) => layer,
+            None => self.create_layer(physical_width, physical_height),
+        };
+        layer.size = [width, height];
+        layer.blurred = false;
+        let texture_id = layer.texture_id;
+
+        let image = ImageHandle {
+            texture: texture_id,
+            uv: Rect::new(0.0, 0.0, 1.0, 1.0),
+            premultiplied: true,
+            width: physical_width,
+            height: physical_height,
+        };
+        self.open_layers.push(OpenLayer {
+            layer,
+            image,
+            batches: mem::take(&mut self.batches),
+            clip_stack: mem::take(&mut self.clip_stack),
+            transform: mem::replace(&mut self.transform, Transform::identity()),
+            transform_stack: mem::take(&mut self.transform_stack),
+            draw_state: self.draw_state,
+        });
+        self.update_clip();
     }
 
-    pub fn add_texture(&mut self, data: &[u8], width: u32, height: u32) -> Rect {
-        if self.texture_cur_x + width >= TEXTURE_SIZE {
-            self.texture_cur_x = 0;
-            self.texture_cur_y += self.texture_cur_max_height;
-            self.texture_cur_max_height = 0;
-        }
-        if self.texture_cur_y + height >= TEXTURE_SIZE {
-            eprintln!("out of texture space");
-        }
-        let dst_x = self.texture_cur_x;
-        let dst_y = self.texture_cur_y;
+    /// Ends the innermost layer and returns it as an image in physical
+    /// pixels. The layer is rendered along with the frame it was drawn in,
+    /// see `begin_layer` for how long the image stays valid.
+    pub fn end_layer(&mut self) -> ImageHandle {
+        let open = self
+            .open_layers
+            .pop()
+            .expect("end_layer called without a matching begin_layer");
+        let mut layer = open.layer;
+        layer.batches = mem::replace(&mut self.batches, open.batches);
+        layer.batches.sort_by_key(|batch| batch.state.z_index);
+        self.clip_stack = open.clip_stack;
+        self.transform = open.transform;
+        self.transform_stack = open.transform_stack;
+        self.draw_state = open.draw_state;
+        self.layers.push(layer);
+        open.image
+    }
 
-        self.texture_cur_x += width;
-        self.texture_cur_max_height = self.texture_cur_max_height.max(height);
+    /// Keeps the texture of a layer ended or begun in this frame out of the
+    /// layer pool, so that its image stays valid until freed with
+    /// `free_image`.
+    pub fn keep_layer(&mut self, image: &ImageHandle) {
+        let layer = self
+            .layers
+            .iter_mut()
+            .chain(self.open_layers.iter_mut().map(|open| &mut open.layer))
+            .find(|layer| layer.texture_id == image.texture)
+            .expect("keep_layer called with an image that is not a layer of this frame");
+        if let Some(texture) = layer.texture.take() {
+            self.image_textures.insert(image.texture, texture);
+        }
+    }
 
-        self.queue.write_texture(
-            wgpu::ImageCopyTexture {
-                texture: &self.texture,
-                mip_level: 0,
-                origin: wgpu::Origin3d {
-                    x: dst_x,
-                    y: dst_y,
-                    z: 0,
-                },
-                aspect: wgpu::TextureAspect::All,
-            },
-            data,
-            wgpu::ImageDataLayout {
-                offset: 0,
-                bytes_per_row: Some(width * 4),
-                rows_per_image: None,
-            },
-            wgpu::Extent3d {
+    /// A layer with its own texture and attachments, empty and not yet
+    /// sized for the vertex shader.
+    fn create_layer(&mut self, width: u32, height: u32) -> Layer {
+        let format = self.view_format();
+        let texture = create_layer_texture(&self.device, format, width, height);
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let texture_id = self.register_texture_view(&view);
+        Layer {
+            texture: Some(texture),
+            texture_id,
+            view,
+            msaa_view: (self.msaa_samples > 1).then(|| {
+                create_render_target(&self.device, format, width, height, self.msaa_samples)
+            }),
+            stencil_view: create_render_target(
+                &self.device,
+                STENCIL_FORMAT,
                 width,
                 height,
-                depth_or_array_layers: 1,
-            },
-        );
-        Rect {
-            x1: dst_x as f32 / TEXTURE_SIZE as f32,
-            y1: dst_y as f32 / TEXTURE_SIZE as f32,
-            x2: (dst_x + width) as f32 / TEXTURE_SIZE as f32,
-            y2: (dst_y + height) as f32 / TEXTURE_SIZE as f32,
+                self.msaa_samples,
+            ),
+            width,
+            height,
+            size: [0.0, 0.0],
+            batches: vec![],
+            blurred: false,
+            blur: None,
         }
     }
 
-    pub fn render(&mut self, view: &wgpu::TextureView) {
-        let mut encoder = self
-            .device
-            .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
-        {
-            let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-                label: None,
-                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                    view,
-                    resolve_target: None,
-                    ops: wgpu::Operations {
-                        load: wgpu::LoadOp::Clear(wgpu::Color {
-                            r: 0.0,
-                            g: 0.0,
-                            b: 0.0,
-                            a: 0.0,
-                        }),
-                        store: wgpu::StoreOp::Store,
-                    },
-                })],
-                depth_stencil_attachment: None,
-                timestamp_writes: None,
-                occlusion_query_set: None,
-            });
-            rpass.set_pipeline(&self.pipeline);
-            if self.n_committed_indices > 0 {
-                rpass.set_bind_group(0, &self.bind_group, &[]);
-                rpass.set_index_buffer(self.index_buf.slice(..), wgpu::IndexFormat::Uint32);
-                rpass.set_vertex_buffer(0, self.vertex_buf.slice(..));
-                rpass.draw_indexed(0..self.n_committed_indices, 0, 0..1);
-            }
-        }
-        self.queue.submit(Some(encoder.finish()));
-    }
+    pub fn draw_path(&mut self, path: Path, style: &StrokeStyle, paint: impl Into<Paint>) {
+        let tolerance = self.tolerance();
+        let path = if style.dashes.is_empty() {
+            path
+        } else {
+            dash_path(&path, &style.dashes, style.dash_offset, tolerance)
+        };
 
-    pub fn draw_path(&mut self, path: Path, color: &Color) {
         let mut geometry: VertexBuffers<Vertex, u32> = VertexBuffers::new();
         let mut tessellator = StrokeTessellator::new();
-        let color_v = [color.r, color.g, color.b, color.a];
+        let color_v = self.begin_paint(&paint.into());
         {
             tessellator
                 .tessellate_path(
                     &path,
-                    &StrokeOptions::default(),
+                    &style.options(tolerance),
                     &mut BuffersBuilder::new(&mut geometry, |vertex: StrokeVertex| Vertex {
                         pos: vertex.position().to_array(),
                         uv: [0.0, 0.0],
@@ -406,30 +2955,19 @@ impl Graphics {
                 .unwrap();
         }
         self.add_geom(&geometry.vertices, &geometry.indices);
+        self.end_paint();
     }
 
-    pub fn fill_path(&mut self, path: Path, color: &Color) {
-        let mut geometry: VertexBuffers<Vertex, u32> = VertexBuffers::new();
-        let mut tessellator = FillTessellator::new();
-        let color_v = [color.r, color.g, color.b, color.a];
-        {
-            tessellator
-                .tessellate_path(
-                    &path,
-                    &FillOptions::default(),
-                    &mut BuffersBuilder::new(&mut geometry, |vertex: FillVertex| Vertex {
-                        pos: vertex.position().to_array(),
-                        uv: [0.0, 0.0],
-                        color: color_v,
-                    }),
-                )
-                .unwrap();
-        }
+    pub fn fill_path(&mut self, path: Path, paint: impl Into<Paint>) {
+        let color_v = self.begin_paint(&paint.into());
+        let options = FillOptions::tolerance(self.tolerance());
+        let geometry = fill_geometry(&path, &options, color_v);
         self.add_geom(&geometry.vertices, &geometry.indices);
+        self.end_paint();
     }
 
-    pub fn fill_rect(&mut self, x1: f32, y1: f32, x2: f32, y2: f32, color: &Color) {
-        let color_v = [color.r, color.g, color.b, color.a];
+    pub fn fill_rect(&mut self, x1: f32, y1: f32, x2: f32, y2: f32, paint: impl Into<Paint>) {
+        let color_v = self.begin_paint(&paint.into());
         let vertices = [
             Vertex {
                 pos: [x1, y1],
@@ -454,6 +2992,7 @@ impl Graphics {
         ];
         let indices = [0u32, 1, 2, 0, 2, 3];
         self.add_geom(&vertices, &indices);
+        self.end_paint();
     }
 
     pub fn fill_tri(
@@ -464,9 +3003,9 @@ impl Graphics {
         y2: f32,
         x3: f32,
         y3: f32,
-        color: &Color,
+        paint: impl Into<Paint>,
     ) {
-        let color_v = [color.r, color.g, color.b, color.a];
+        let color_v = self.begin_paint(&paint.into());
         let vertices = [
             Vertex {
                 pos: [x1, y1],
@@ -486,27 +3025,46 @@ impl Graphics {
         ];
         let indices = [0u32, 1, 2];
         self.add_geom(&vertices, &indices);
+        self.end_paint();
     }
 
-    pub fn draw_line(&mut self, x1: f32, y1: f32, x2: f32, y2: f32, color: &Color) {
+    pub fn draw_line(
+        &mut self,
+        x1: f32,
+        y1: f32,
+        x2: f32,
+        y2: f32,
+        style: &StrokeStyle,
+        paint: impl Into<Paint>,
+    ) {
         let mut builder = Path::builder();
         builder.begin(point(x1, y1));
         builder.line_to(point(x2, y2));
-        builder.close();
+        builder.end(false);
         let path = builder.build();
-        self.draw_path(path, color);
+        self.draw_path(path, style, paint);
     }
 
-    pub fn draw_rect(&mut self, x1: f32, y1: f32, x2: f32, y2: f32, color: &Color) {
+    pub fn draw_rect(
+        &mut self,
+        x1: f32,
+        y1: f32,
+        x2: f32,
+        y2: f32,
+        style: &StrokeStyle,
+        paint: impl Into<Paint>,
+    ) {
         let mut builder = Path::builder();
         builder.add_rectangle(
             &Box2D::new(Point2D::new(x1, y1), Point2D::new(x2, y2)),
             Winding::Positive,
         );
         let path = builder.build();
-        self.draw_path(path, color);
+        self.draw_path(path, style, paint);
     }
 
+    // takes its corners like `fill_tri` does
+    #[allow(clippy::too_many_arguments)]
     pub fn draw_tri(
         &mut self,
         x1: f32,
@@ -515,7 +3073,8 @@ impl Graphics {
         y2: f32,
         x3: f32,
         y3: f32,
-        color: &Color,
+        style: &StrokeStyle,
+        paint: impl Into<Paint>,
     ) {
         let mut builder = Path::builder();
         builder.begin(point(x1, y1));
@@ -524,7 +3083,401 @@ impl Graphics {
         builder.line_to(point(x1, y1));
         builder.close();
         let path = builder.build();
-        self.draw_path(path, color);
+        self.draw_path(path, style, paint);
+    }
+
+    pub fn fill_rounded_rect(&mut self, rect: Rect, radii: &BorderRadii, paint: impl Into<Paint>) {
+        self.fill_path(rounded_rect_path(&rect, radii), paint);
+    }
+
+    pub fn draw_rounded_rect(
+        &mut self,
+        rect: Rect,
+        radii: &BorderRadii,
+        style: &StrokeStyle,
+        paint: impl Into<Paint>,
+    ) {
+        self.draw_path(rounded_rect_path(&rect, radii), style, paint);
+    }
+
+    pub fn fill_circle(&mut self, x: f32, y: f32, radius: f32, paint: impl Into<Paint>) {
+        self.fill_ellipse(x, y, radius, radius, paint);
+    }
+
+    pub fn draw_circle(
+        &mut self,
+        x: f32,
+        y: f32,
+        radius: f32,
+        style: &StrokeStyle,
+        paint: impl Into<Paint>,
+    ) {
+        self.draw_ellipse(x, y, radius, radius, style, paint);
+    }
+
+    pub fn fill_ellipse(
+        &mut self,
+        x: f32,
+        y: f32,
+        radius_x: f32,
+        radius_y: f32,
+        paint: impl Into<Paint>,
+    ) {
+        self.fill_path(ellipse_path(x, y, radius_x, radius_y), paint);
+    }
+
+    pub fn draw_ellipse(
+        &mut self,
+        x: f32,
+        y: f32,
+        radius_x: f32,
+        radius_y: f32,
+        style: &StrokeStyle,
+        paint: impl Into<Paint>,
+    ) {
+        self.draw_path(ellipse_path(x, y, radius_x, radius_y), style, paint);
+    }
+
+    /// Strokes the part of a circle from `start_angle` sweeping through
+    /// `sweep_angle`. Angles are in radians, clockwise from the +x axis.
+    pub fn draw_arc(
+        &mut self,
+        center: Point,
+        radius: f32,
+        start_angle: f32,
+        sweep_angle: f32,
+        style: &StrokeStyle,
+        paint: impl Into<Paint>,
+    ) {
+        let path = arc_path(center, radius, start_angle, sweep_angle, false);
+        self.draw_path(path, style, paint);
+    }
+
+    /// Fills the wedge between the center and the arc drawn by `draw_arc`.
+    pub fn fill_pie(
+        &mut self,
+        center: Point,
+        radius: f32,
+        start_angle: f32,
+        sweep_angle: f32,
+        paint: impl Into<Paint>,
+    ) {
+        let path = arc_path(center, radius, start_angle, sweep_angle, true);
+        self.fill_path(path, paint);
+    }
+
+    /// Draws the blurred shadow of a rounded rect with corner `radius`, as
+    /// CSS `box-shadow` does. `blur` is the blur radius, twice the standard
+    /// deviation of the blur, and `spread` grows the rect before blurring.
+    pub fn draw_box_shadow(
+        &mut self,
+        rect: Rect,
+        radius: f32,
+        blur: f32,
+        spread: f32,
+        color: &Color,
+    ) {
+        let rect = Rect::new(
+            rect.x1 - spread,
+            rect.y1 - spread,
+            rect.x2 + spread,
+            rect.y2 + spread,
+        );
+        if rect.is_empty() {
+            return;
+        }
+        let max_radius = (rect.x2 - rect.x1).min(rect.y2 - rect.y1) / 2.0;
+        let radius = (radius + spread).clamp(0.0, max_radius);
+        if blur <= 0.0 {
+            let radii = BorderRadii::new(radius);
+            self.fill_rounded_rect(rect, &radii, color);
+            return;
+        }
+
+        let sigma = blur / 2.0;
+        let mut shadow = GradientUniform::zeroed();
+        shadow.kind = GRADIENT_BOX_SHADOW;
+        shadow.params = [rect.x1, rect.y1, rect.x2, rect.y2];
+        shadow.shadow_radius = radius;
+        shadow.shadow_sigma = sigma;
+        self.use_gradient(shadow);
+        // the shadow fades out within 3 standard deviations
+        let margin = 3.0 * sigma;
+        let (x1, y1) = (rect.x1 - margin, rect.y1 - margin);
+        let (x2, y2) = (rect.x2 + margin, rect.y2 + margin);
+        let color_v = color.to_linear();
+        let vertices = [
+            Vertex {
+                pos: [x1, y1],
+                uv: [0.0, 0.0],
+                color: color_v,
+            },
+            Vertex {
+                pos: [x1, y2],
+                uv: [0.0, 0.0],
+                color: color_v,
+            },
+            Vertex {
+                pos: [x2, y2],
+                uv: [0.0, 0.0],
+                color: color_v,
+            },
+            Vertex {
+                pos: [x2, y1],
+                uv: [0.0, 0.0],
+                color: color_v,
+            },
+        ];
+        let indices = [0u32, 1, 2, 0, 2, 3];
+        self.add_geom(&vertices, &indices);
+        self.end_paint();
+    }
+
+    /// Draws the `src` part of `image`, in image pixels, stretched over
+    /// `dst`. The whole image is drawn if `src` is `None`. Colors are
+    /// multiplied by `tint`.
+    pub fn draw_image(&mut self, image: &ImageHandle, src: Option<Rect>, dst: Rect, tint: &Color) {
+        let src = src.unwrap_or(Rect::new(0.0, 0.0, image.width as f32, image.height as f32));
+        let mut geometry = VertexBuffers::new();
+        let color_v = image.vertex_color(tint);
+        push_image_quad(&mut geometry, image, &src, &dst, color_v);
+        self.add_image_geom(&geometry, image);
+    }
+
+    /// Draws `frame` of `sheet` stretched over `dst`.
+    pub fn draw_sheet_frame(&mut self, sheet: &SpriteSheet, frame: usize, dst: Rect, tint: &Color) {
+        self.draw_image(&sheet.image, Some(sheet.frame_rect(frame)), dst, tint);
+    }
+
+    /// Draws the current frame of `animation` stretched over `dst`.
+    pub fn draw_animation(&mut self, animation: &Animation, dst: Rect, tint: &Color) {
+        self.draw_sheet_frame(&animation.sheet, animation.current_frame(), dst, tint);
+    }
+
+    /// Draws the chunks of `map` that are in the frame, or in the current
+    /// layer, and not clipped away. Chunks whose tiles changed are rebuilt
+    /// first. The map's top left corner is at the origin of the current
+    /// transform.
+    pub fn draw_tile_map(&mut self, map: &mut TileMap) {
+        map.dropped_geometry
+            .get_or_insert_with(|| self.dropped_geometry.clone());
+        let Some(inverse) = self.transform.inverse() else {
+            return;
+        };
+        let [width, height] = self
+            .open_layers
+            .last()
+            .map_or([self.width, self.height], |open| open.layer.size);
+        let mut viewport = Rect::new(0.0, 0.0, width, height);
+        if let Some(clip) = self.clip_rect() {
+            viewport = viewport.intersect(clip);
+        }
+        if viewport.is_empty() {
+            return;
+        }
+        // bounds of the viewport in map coordinates
+        let corners = [
+            point(viewport.x1, viewport.y1),
+            point(viewport.x2, viewport.y1),
+            point(viewport.x1, viewport.y2),
+            point(viewport.x2, viewport.y2),
+        ]
+        .map(|corner| inverse.transform_point(corner));
+        let bounds = Box2D::from_points(corners);
+
+        let chunk_width = map.tile_width * TILE_CHUNK_SIZE as f32;
+        let chunk_height = map.tile_height * TILE_CHUNK_SIZE as f32;
+        let chunk_range = |min: f32, max: f32, size: f32, n_chunks: u32| {
+            let start = (min / size).floor().clamp(0.0, n_chunks as f32) as u32;
+            let end = (max / size).ceil().clamp(0.0, n_chunks as f32) as u32;
+            start..end
+        };
+        let chunks_x = chunk_range(
+            bounds.min.x,
+            bounds.max.x,
+            chunk_width,
+            map.width.div_ceil(TILE_CHUNK_SIZE),
+        );
+        let chunks_y = chunk_range(
+            bounds.min.y,
+            bounds.max.y,
+            chunk_height,
+            map.height.div_ceil(TILE_CHUNK_SIZE),
+        );
+
+        let image = map.tileset.image;
+        let premultiplied = mem::replace(&mut self.draw_state.premultiplied, image.premultiplied);
+        for chunk_y in chunks_y {
+            for chunk_x in chunks_x.clone() {
+                let index = map.chunk_index(chunk_x, chunk_y);
+                if map.chunks[index].dirty {
+                    if let Some(geometry) = map.chunks[index].geometry.take() {
+                        self.free_static_geom(geometry);
+                    }
+                    let geometry = map.chunk_geometry(chunk_x, chunk_y);
+                    if !geometry.indices.is_empty() {
+                        map.chunks[index].geometry =
+                            Some(self.create_static_geom(&geometry.vertices, &geometry.indices));
+                    }
+                    map.chunks[index].dirty = false;
+                }
+                if let Some(geometry) = map.chunks[index].geometry {
+                    self.draw_static_geom(geometry, image.texture);
+                }
+            }
+        }
+        self.draw_state.premultiplied = premultiplied;
+    }
+
+    /// Releases the GPU geometry of `map`, as dropping it does.
+    pub fn free_tile_map(&mut self, mut map: TileMap) {
+        for chunk in mem::take(&mut map.chunks) {
+            if let Some(geometry) = chunk.geometry {
+                self.free_static_geom(geometry);
+            }
+        }
+    }
+
+    /// Draws the sprites of `batch` with one instanced draw call. Sprites are
+    /// mapped through the current transform.
+    pub fn draw_sprites(&mut self, batch: &SpriteBatch) {
+        let t = self.transform;
+        let instances = batch
+            .sprites
+            .iter()
+            .map(|sprite| {
+                let (sin, cos) = sprite.rotation.sin_cos();
+                let axis_x = t.transform_vector(vector(cos, sin) * sprite.width);
+                let axis_y = t.transform_vector(vector(-sin, cos) * sprite.height);
+                let uv = &sprite.uv;
+                let color = &sprite.color;
+                SpriteInstance {
+                    axis_x: axis_x.to_array(),
+                    axis_y: axis_y.to_array(),
+                    center: t.transform_point(point(sprite.x, sprite.y)).to_array(),
+                    uv: [uv.x1, uv.y1, uv.x2, uv.y2],
+                    color: color.to_linear(),
+                }
+            })
+            .collect::<Vec<_>>();
+        if !instances.is_empty() {
+            self.push_instances(&instances, batch.texture);
+        }
+    }
+
+    /// Draws `image` over `dst` with the parts outside of `insets` kept at
+    /// their size, as for the frame of a button or panel. The edges and
+    /// center are stretched to fit. The corners shrink proportionally if
+    /// `dst` is too small for them.
+    pub fn draw_nine_slice(
+        &mut self,
+        image: &ImageHandle,
+        insets: &Insets,
+        dst: Rect,
+        tint: &Color,
+    ) {
+        self.nine_slice(image, insets, dst, tint, false);
+    }
+
+    /// Like `draw_nine_slice`, but repeats the edges and center instead of
+    /// stretching them.
+    pub fn draw_nine_slice_tiled(
+        &mut self,
+        image: &ImageHandle,
+        insets: &Insets,
+        dst: Rect,
+        tint: &Color,
+    ) {
+        self.nine_slice(image, insets, dst, tint, true);
+    }
+
+    fn nine_slice(
+        &mut self,
+        image: &ImageHandle,
+        insets: &Insets,
+        dst: Rect,
+        tint: &Color,
+        tile: bool,
+    ) {
+        let (width, height) = (image.width as f32, image.height as f32);
+        let corner_scale = |size: f32, start: f32, end: f32| {
+            if start + end > size {
+                size / (start + end)
+            } else {
+                1.0
+            }
+        };
+        let sx = corner_scale(dst.x2 - dst.x1, insets.left, insets.right);
+        let sy = corner_scale(dst.y2 - dst.y1, insets.top, insets.bottom);
+        let src_x = [0.0, insets.left, width - insets.right, width];
+        let src_y = [0.0, insets.top, height - insets.bottom, height];
+        let dst_x = [
+            dst.x1,
+            dst.x1 + insets.left * sx,
+            dst.x2 - insets.right * sx,
+            dst.x2,
+        ];
+        let dst_y = [
+            dst.y1,
+            dst.y1 + insets.top * sy,
+            dst.y2 - insets.bottom * sy,
+            dst.y2,
+        ];
+
+        let mut geometry = VertexBuffers::new();
+        let color_v = image.vertex_color(tint);
+        for row in 0..3 {
+            for col in 0..3 {
+                let src = Rect::new(src_x[col], src_y[row], src_x[col + 1], src_y[row + 1]);
+                let dst = Rect::new(dst_x[col], dst_y[row], dst_x[col + 1], dst_y[row + 1]);
+                if src.is_empty() || dst.is_empty() {
+                    continue;
+                }
+                // corners are never tiled, edges only along their length
+                let tile_width = if tile && col == 1 {
+                    src.x2 - src.x1
+                } else {
+                    dst.x2 - dst.x1
+                };
+                let tile_height = if tile && row == 1 {
+                    src.y2 - src.y1
+                } else {
+                    dst.y2 - dst.y1
+                };
+
+                // counted up front, as adding a tile to a coordinate much
+                // larger than it may not advance it
+                let n_cols = ((dst.x2 - dst.x1) / tile_width).ceil() as u32;
+                let n_rows = ((dst.y2 - dst.y1) / tile_height).ceil() as u32;
+                for i in 0..n_rows {
+                    let y = dst.y1 + i as f32 * tile_height;
+                    let y2 = (y + tile_height).min(dst.y2);
+                    let src_y2 = src.y1 + (src.y2 - src.y1) * (y2 - y) / tile_height;
+                    for j in 0..n_cols {
+                        let x = dst.x1 + j as f32 * tile_width;
+                        let x2 = (x + tile_width).min(dst.x2);
+                        let src_x2 = src.x1 + (src.x2 - src.x1) * (x2 - x) / tile_width;
+                        push_image_quad(
+                            &mut geometry,
+                            image,
+                            &Rect::new(src.x1, src.y1, src_x2, src_y2),
+                            &Rect::new(x, y, x2, y2),
+                            color_v,
+                        );
+                    }
+                }
+            }
+        }
+        self.add_image_geom(&geometry, image);
+    }
+
+    /// Adds quads from `push_image_quad`, blended as the image stores
+    /// alpha.
+    fn add_image_geom(&mut self, geometry: &VertexBuffers<Vertex, u32>, image: &ImageHandle) {
+        let premultiplied = mem::replace(&mut self.draw_state.premultiplied, image.premultiplied);
+        self.ensure_pipeline(self.draw_state.pipeline_key());
+        self.add_geom_with_texture(&geometry.vertices, &geometry.indices, image.texture);
+        self.draw_state.premultiplied = premultiplied;
     }
 
     pub fn draw_fitted_text_line(
@@ -593,11 +3546,29 @@ impl Graphics {
                     tex.push(0xff);
                     tex.push(v);
                 }
-                let rect = self.add_texture(&tex, metrics.width as u32, metrics.height as u32);
-                self.font_atlas.insert(glyph.key, rect);
+                let width = metrics.width as u32;
+                let height = metrics.height as u32;
+                let (page, x, y) = self.alloc_in_pages(true, width, height);
+                let texture = &self.glyph_pages[page].texture;
+                self.write_atlas_texture(texture, x, y, &tex, width, height);
+                self.font_atlas.insert(
+                    glyph.key,
+                    AtlasEntry {
+                        page,
+                        x,
+                        y,
+                        width,
+                        height,
+                        last_used: self.frame,
+                    },
+                );
             }
-            let uv_rect = &self.font_atlas[&glyph.key];
-            let color_v = [color.r, color.g, color.b, color.a];
+            let entry = self.font_atlas.get_mut(&glyph.key).unwrap();
+            entry.last_used = self.frame;
+            let glyph_page = &self.glyph_pages[entry.page];
+            let uv_rect = glyph_page.uv_rect(entry.x, entry.y, entry.width, entry.height);
+            let texture = glyph_page.texture_id;
+            let color_v = color.to_linear();
             let vertices = [
                 Vertex {
                     pos: [x + glyph.x / self.scale, y + glyph.y / self.scale],
@@ -630,7 +3601,382 @@ impl Graphics {
                 },
             ];
             let indices = [0u32, 1, 2, 0, 2, 3];
-            self.add_geom(&vertices, &indices);
+            self.add_geom_with_texture(&vertices, &indices, texture);
+        }
+    }
+}
+
+fn view_format(config: &wgpu::SurfaceConfiguration) -> wgpu::TextureFormat {
+    config
+        .view_formats
+        .first()
+        .copied()
+        .unwrap_or(config.format)
+}
+
+/// Geometry buffers are reallocated by `commit_geom` whenever a frame
+/// outgrows them, so they only need an initial size here.
+fn create_geom_buffer(
+    device: &wgpu::Device,
+    size: wgpu::BufferAddress,
+    usage: wgpu::BufferUsages,
+) -> wgpu::Buffer {
+    device.create_buffer(&wgpu::BufferDescriptor {
+        label: None,
+        size,
+        usage: usage | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    })
+}
+
+/// WGSL declarations of the `GRADIENT_*` kinds, prepended to `shader.wgsl`
+/// so that both sides use the same values.
+fn gradient_kind_constants() -> String {
+    [
+        ("GRADIENT_LINEAR", GRADIENT_LINEAR),
+        ("GRADIENT_RADIAL", GRADIENT_RADIAL),
+        ("GRADIENT_CONIC", GRADIENT_CONIC),
+        ("GRADIENT_BOX_SHADOW", GRADIENT_BOX_SHADOW),
+    ]
+    .iter()
+    .map(|(name, kind)| format!("const {}: u32 = {}u;\n", name, kind))
+    .collect()
+}
+
+/// Bind group of a gradient in `gradient_buf` and a target size in
+/// `uniform_buf`, both selected by dynamic offsets.
+fn create_gradient_bind_group(
+    device: &wgpu::Device,
+    layout: &wgpu::BindGroupLayout,
+    gradient_buf: &wgpu::Buffer,
+    uniform_buf: &wgpu::Buffer,
+) -> wgpu::BindGroup {
+    device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: None,
+        layout,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                    buffer: gradient_buf,
+                    offset: 0,
+                    size: wgpu::BufferSize::new(mem::size_of::<GradientUniform>() as u64),
+                }),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                    buffer: uniform_buf,
+                    offset: 0,
+                    size: wgpu::BufferSize::new(mem::size_of::<[f32; 2]>() as u64),
+                }),
+            },
+        ],
+    })
+}
+
+fn rounded_rect_path(rect: &Rect, radii: &BorderRadii) -> Path {
+    let mut builder = Path::builder();
+    builder.add_rounded_rectangle(
+        &Box2D::new(
+            Point2D::new(rect.x1, rect.y1),
+            Point2D::new(rect.x2, rect.y2),
+        ),
+        radii,
+        Winding::Positive,
+    );
+    builder.build()
+}
+
+fn ellipse_path(x: f32, y: f32, radius_x: f32, radius_y: f32) -> Path {
+    let mut builder = Path::builder();
+    builder.add_ellipse(
+        point(x, y),
+        vector(radius_x, radius_y),
+        Angle::zero(),
+        Winding::Positive,
+    );
+    builder.build()
+}
+
+/// Arc of a circle, closed through the center if `pie` is set.
+fn arc_path(center: Point, radius: f32, start_angle: f32, sweep_angle: f32, pie: bool) -> Path {
+    let arc = Arc {
+        center,
+        radii: vector(radius, radius),
+        start_angle: Angle::radians(start_angle),
+        sweep_angle: Angle::radians(sweep_angle),
+        x_rotation: Angle::zero(),
+    };
+    let mut builder = Path::builder();
+    if pie {
+        builder.begin(arc.center);
+        builder.line_to(arc.from());
+    } else {
+        builder.begin(arc.from());
+    }
+    arc.for_each_quadratic_bezier(&mut |curve| {
+        builder.quadratic_bezier_to(curve.ctrl, curve.to);
+    });
+    builder.end(pie);
+    builder.build()
+}
+
+/// Splits `path` into the "on" intervals of a dash pattern. Curves are
+/// flattened to lines first so distances along them can be measured. The
+/// path is returned whole if the pattern has no length, or would split it
+/// into more than `MAX_DASHES` pieces.
+fn dash_path(path: &Path, dashes: &[f32], offset: f32, tolerance: f32) -> Path {
+    // distances are in f64 so that short dashes still advance along long
+    // segments
+    let mut pattern: Vec<f64> = dashes.iter().map(|&d| d.max(0.0) as f64).collect();
+    if pattern.len() % 2 == 1 {
+        pattern.extend_from_within(..);
+    }
+    let total: f64 = pattern.iter().sum();
+    if total <= 0.0 {
+        return path.clone();
+    }
+    let path_length: f64 = path
+        .iter()
+        .flattened(tolerance)
+        .map(|event| match event {
+            PathEvent::Line { from, to } => (to - from).length() as f64,
+            PathEvent::End {
+                last,
+                first,
+                close: true,
+            } => (first - last).length() as f64,
+            _ => 0.0,
+        })
+        .sum();
+    if path_length / total * pattern.len() as f64 > MAX_DASHES {
+        eprintln!(
+            "dash pattern of length {} is too short for a path of length {}, drawing it solid",
+            total, path_length
+        );
+        return path.clone();
+    }
+
+    let mut builder = Path::builder();
+    let mut drawing = false;
+    let mut index = 0;
+    let mut remaining = 0.0;
+    for event in path.iter().flattened(tolerance) {
+        let (from, to) = match event {
+            PathEvent::Begin { at } => {
+                // every subpath starts at the same point of the pattern
+                index = 0;
+                remaining = (offset as f64).rem_euclid(total);
+                while remaining > pattern[index] {
+                    remaining -= pattern[index];
+                    index = (index + 1) % pattern.len();
+                }
+                remaining = pattern[index] - remaining;
+                if index % 2 == 0 {
+                    builder.begin(at);
+                    drawing = true;
+                }
+                continue;
+            }
+            PathEvent::Line { from, to } => (from, to),
+            PathEvent::End { last, first, close } => {
+                if !close {
+                    if drawing {
+                        builder.end(false);
+                        drawing = false;
+                    }
+                    continue;
+                }
+                (last, first)
+            }
+            _ => unreachable!("flattened paths only contain lines"),
+        };
+
+        // distance from the start of the segment
+        let length = (to - from).length() as f64;
+        let mut travelled = 0.0;
+        while length - travelled > remaining {
+            travelled += remaining;
+            let at = from.lerp(to, (travelled / length) as f32);
+            if drawing {
+                builder.line_to(at);
+                builder.end(false);
+                drawing = false;
+            } else {
+                builder.begin(at);
+                drawing = true;
+            }
+            index = (index + 1) % pattern.len();
+            remaining = pattern[index];
+        }
+        remaining -= length - travelled;
+        if drawing {
+            builder.line_to(to);
+        }
+
+        if let PathEvent::End { .. } = event {
+            if drawing {
+                builder.end(false);
+                drawing = false;
+            }
         }
     }
+    builder.build()
+}
+
+/// Adds a quad drawing the `src` part of `image`, in image pixels, over
+/// `dst`.
+fn push_image_quad(
+    geometry: &mut VertexBuffers<Vertex, u32>,
+    image: &ImageHandle,
+    src: &Rect,
+    dst: &Rect,
+    color: [f32; 4],
+) {
+    let uv = &image.uv;
+    let u = |x: f32| uv.x1 + (uv.x2 - uv.x1) * x / image.width as f32;
+    let v = |y: f32| uv.y1 + (uv.y2 - uv.y1) * y / image.height as f32;
+    let start = geometry.vertices.len() as u32;
+    geometry.vertices.extend_from_slice(&[
+        Vertex {
+            pos: [dst.x1, dst.y1],
+            uv: [u(src.x1), v(src.y1)],
+            color,
+        },
+        Vertex {
+            pos: [dst.x1, dst.y2],
+            uv: [u(src.x1), v(src.y2)],
+            color,
+        },
+        Vertex {
+            pos: [dst.x2, dst.y2],
+            uv: [u(src.x2), v(src.y2)],
+            color,
+        },
+        Vertex {
+            pos: [dst.x2, dst.y1],
+            uv: [u(src.x2), v(src.y1)],
+            color,
+        },
+    ]);
+    geometry
+        .indices
+        .extend([0, 1, 2, 0, 2, 3].map(|i| start + i));
+}
+
+fn fill_geometry(
+    path: &Path,
+    options: &FillOptions,
+    color: [f32; 4],
+) -> VertexBuffers<Vertex, u32> {
+    let mut geometry: VertexBuffers<Vertex, u32> = VertexBuffers::new();
+    let mut tessellator = FillTessellator::new();
+    tessellator
+        .tessellate_path(
+            path,
+            options,
+            &mut BuffersBuilder::new(&mut geometry, |vertex: FillVertex| Vertex {
+                pos: vertex.position().to_array(),
+                uv: [0.0, 0.0],
+                color,
+            }),
+        )
+        .unwrap();
+    geometry
+}
+
+/// Resources of the blur passes of `layer`.
+fn create_layer_blur(
+    device: &wgpu::Device,
+    layout: &wgpu::BindGroupLayout,
+    sampler: &wgpu::Sampler,
+    format: wgpu::TextureFormat,
+    layer: &Layer,
+) -> LayerBlur {
+    let temp_view = create_layer_texture(device, format, layer.width, layer.height)
+        .create_view(&wgpu::TextureViewDescriptor::default());
+    let uniform_bufs = [(); 2].map(|_| {
+        create_geom_buffer(
+            device,
+            mem::size_of::<BlurUniform>() as u64,
+            wgpu::BufferUsages::UNIFORM,
+        )
+    });
+    let bind_group = |uniform_buf: &wgpu::Buffer, view: &wgpu::TextureView| {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: None,
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: uniform_buf.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::Sampler(sampler),
+                },
+            ],
+        })
+    };
+    let bind_groups = [
+        bind_group(&uniform_bufs[0], &layer.view),
+        bind_group(&uniform_bufs[1], &temp_view),
+    ];
+    LayerBlur {
+        view: temp_view,
+        uniform_bufs,
+        bind_groups,
+    }
+}
+
+/// A texture that can be both drawn into and sampled.
+fn create_layer_texture(
+    device: &wgpu::Device,
+    format: wgpu::TextureFormat,
+    width: u32,
+    height: u32,
+) -> wgpu::Texture {
+    device.create_texture(&wgpu::TextureDescriptor {
+        label: None,
+        size: wgpu::Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format,
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+        view_formats: &[],
+    })
+}
+
+fn create_render_target(
+    device: &wgpu::Device,
+    format: wgpu::TextureFormat,
+    width: u32,
+    height: u32,
+    sample_count: u32,
+) -> wgpu::TextureView {
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+        label: None,
+        size: wgpu::Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count,
+        dimension: wgpu::TextureDimension::D2,
+        format,
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+        view_formats: &[],
+    });
+    texture.create_view(&wgpu::TextureViewDescriptor::default())
 }
@@ -1,2 +1,3 @@
 pub mod app;
+mod atlas;
 pub mod graphics;
@@ -1,11 +1,4 @@
-struct VertexOutput {
-    @location(0) uv: vec2<f32>,
-    @location(1) color: vec4<f32>,
-    @builtin(position) position: vec4<f32>,
-};
-
-@group(0) @binding(0)
-var<uniform> size: vec2<f32>;
+// declarations shared with materials are in common.wgsl
 
 @vertex
 fn vs_main(
@@ -16,6 +9,7 @@ fn vs_main(
     var result: VertexOutput;
     result.uv = uv;
     result.color = color;
+    result.pos = position;
     result.position = vec4<f32>(
         (position.x / size.x) * 2.0 - 1.0,
         1.0 - (position.y / size.y) * 2.0,
@@ -23,13 +17,163 @@ fn vs_main(
     return result;
 }
 
-@group(0) @binding(1)
-var tex: texture_2d<f32>;
-@group(0) @binding(2)
-var samp: sampler;
+@vertex
+fn vs_static(
+    @location(0) local_position: vec2<f32>,
+    @location(1) uv: vec2<f32>,
+    @location(2) color: vec4<f32>,
+    @location(3) axis_x: vec2<f32>,
+    @location(4) axis_y: vec2<f32>,
+    @location(5) origin: vec2<f32>,
+) -> VertexOutput {
+    let position = origin + axis_x * local_position.x + axis_y * local_position.y;
+    var result: VertexOutput;
+    result.uv = uv;
+    result.color = color;
+    result.pos = position;
+    result.position = vec4<f32>(
+        (position.x / size.x) * 2.0 - 1.0,
+        1.0 - (position.y / size.y) * 2.0,
+        0.0, 1.0);
+    return result;
+}
+
+@vertex
+fn vs_sprite(
+    @location(0) corner: vec2<f32>,
+    @location(1) axis_x: vec2<f32>,
+    @location(2) axis_y: vec2<f32>,
+    @location(3) center: vec2<f32>,
+    @location(4) uv_rect: vec4<f32>,
+    @location(5) color: vec4<f32>,
+) -> VertexOutput {
+    let position = center + axis_x * corner.x + axis_y * corner.y;
+    var result: VertexOutput;
+    result.uv = mix(uv_rect.xy, uv_rect.zw, corner + 0.5);
+    result.color = color;
+    result.pos = position;
+    result.position = vec4<f32>(
+        (position.x / size.x) * 2.0 - 1.0,
+        1.0 - (position.y / size.y) * 2.0,
+        0.0, 1.0);
+    return result;
+}
+
+// kind 0 is a solid color, else one of the GRADIENT_* constants, which are
+// prepended from graphics.rs
+struct Gradient {
+    kind: u32,
+    n_stops: u32,
+    shadow_radius: f32,
+    shadow_sigma: f32,
+    inverse_transform: array<vec4<f32>, 2>,
+    params: vec4<f32>,
+    offsets: array<vec4<f32>, 2>,
+    colors: array<vec4<f32>, 8>,
+};
+
+@group(1) @binding(0)
+var<uniform> gradient: Gradient;
+
+const TAU: f32 = 6.283185307179586;
+
+fn gradient_color(pos: vec2<f32>) -> vec4<f32> {
+    let p = vec2<f32>(
+        dot(gradient.inverse_transform[0].xyz, vec3<f32>(pos, 1.0)),
+        dot(gradient.inverse_transform[1].xyz, vec3<f32>(pos, 1.0)));
+    let params = gradient.params;
+    if gradient.kind == GRADIENT_BOX_SHADOW {
+        let shadow = box_shadow(params.xy, params.zw, p, gradient.shadow_sigma, gradient.shadow_radius);
+        return vec4<f32>(1.0, 1.0, 1.0, shadow);
+    }
+
+    var t: f32;
+    switch gradient.kind {
+        case GRADIENT_LINEAR: {
+            let d = params.zw - params.xy;
+            t = dot(p - params.xy, d) / max(dot(d, d), 1e-6);
+        }
+        case GRADIENT_RADIAL: {
+            t = length(p - params.xy) / max(params.z, 1e-6);
+        }
+        default: {
+            let d = p - params.xy;
+            t = fract((atan2(d.y, d.x) - params.z) / TAU);
+        }
+    }
+    t = clamp(t, 0.0, 1.0);
+
+    var color = gradient.colors[0];
+    for (var i = 1u; i < gradient.n_stops; i++) {
+        let start = gradient.offsets[(i - 1u) / 4u][(i - 1u) % 4u];
+        let end = gradient.offsets[i / 4u][i % 4u];
+        if t > start {
+            let s = clamp((t - start) / max(end - start, 1e-6), 0.0, 1.0);
+            color = mix(gradient.colors[i - 1u], gradient.colors[i], s);
+        }
+    }
+    return color;
+}
+
+// Box shadow approximation by Evan Wallace: the blur is exact along x, using
+// erf, and integrated numerically along y.
+fn erf(x: vec2<f32>) -> vec2<f32> {
+    let s = sign(x);
+    let a = abs(x);
+    var r = 1.0 + (0.278393 + (0.230389 + 0.078108 * (a * a)) * a) * a;
+    r = r * r;
+    return s - s / (r * r);
+}
+
+fn gaussian(x: f32, sigma: f32) -> f32 {
+    return exp(-(x * x) / (2.0 * sigma * sigma)) / (sqrt(TAU) * sigma);
+}
+
+// blurred coverage of a row of the rounded box, at x from the center
+fn box_shadow_x(x: f32, y: f32, sigma: f32, corner: f32, half_size: vec2<f32>) -> f32 {
+    let delta = min(half_size.y - corner - abs(y), 0.0);
+    let curved = half_size.x - corner + sqrt(max(0.0, corner * corner - delta * delta));
+    let integral = 0.5 + 0.5 * erf((x + vec2<f32>(-curved, curved)) * (sqrt(0.5) / sigma));
+    return integral.y - integral.x;
+}
+
+fn box_shadow(lower: vec2<f32>, upper: vec2<f32>, point: vec2<f32>, sigma: f32, corner: f32) -> f32 {
+    let center = (lower + upper) * 0.5;
+    let half_size = (upper - lower) * 0.5;
+    let p = point - center;
+
+    // only rows within 3 sigma contribute
+    let low = p.y - half_size.y;
+    let high = p.y + half_size.y;
+    let start = clamp(-3.0 * sigma, low, high);
+    let end = clamp(3.0 * sigma, low, high);
+
+    let step = (end - start) / 4.0;
+    var y = start + step * 0.5;
+    var value = 0.0;
+    for (var i = 0; i < 4; i++) {
+        value += box_shadow_x(p.x, p.y - y, sigma, corner, half_size) * gaussian(y, sigma) * step;
+        y += step;
+    }
+    return value;
+}
+
+fn fragment_color(vertex: VertexOutput) -> vec4<f32> {
+    let color = textureSample(tex, samp, vertex.uv) * vertex.color;
+    if gradient.kind == 0u {
+        return color;
+    }
+    return color * gradient_color(vertex.pos);
+}
 
 @fragment
 fn fs_main(vertex: VertexOutput) -> @location(0) vec4<f32> {
-    return textureSample(tex, samp, vertex.uv) * vertex.color;
-    //return vertex.color;
+    return fragment_color(vertex);
+}
+
+// for blend modes that need the color premultiplied by alpha
+@fragment
+fn fs_premultiplied(vertex: VertexOutput) -> @location(0) vec4<f32> {
+    let color = fragment_color(vertex);
+    return vec4<f32>(color.rgb * color.a, color.a);
 }
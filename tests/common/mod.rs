@@ -0,0 +1,128 @@
+//! Golden-image snapshots of draw calls, shared by the integration tests.
+
+use diode::graphics::Graphics;
+use std::path::{Path, PathBuf};
+
+/// Set this environment variable to overwrite stored snapshots with the
+/// current output instead of comparing against them.
+pub const UPDATE_ENV_VAR: &str = "DIODE_UPDATE_SNAPSHOTS";
+
+pub struct Snapshot {
+    pub width: u32,
+    pub height: u32,
+    pub scale: f32,
+    /// Largest per-channel difference (0-255) still counted as a match.
+    pub tolerance: u8,
+}
+impl Default for Snapshot {
+    fn default() -> Self {
+        Self {
+            width: 256,
+            height: 256,
+            scale: 1.0,
+            tolerance: 2,
+        }
+    }
+}
+
+impl Snapshot {
+    /// Runs `draw` against a headless `Graphics` and returns the RGBA frame.
+    pub fn render(&self, draw: impl FnOnce(&mut Graphics)) -> Vec<u8> {
+        let mut gfx = Graphics::headless(self.width, self.height, self.scale);
+        draw(&mut gfx);
+        gfx.commit_geom();
+        gfx.render_to_image()
+    }
+
+    /// Renders `draw` and compares it with the PNG at `path`, panicking on a
+    /// mismatch or if the PNG does not exist. The PNG is written instead if
+    /// `UPDATE_ENV_VAR` is set. On mismatch the rendered frame and a diff
+    /// image are written next to it as `<name>.actual.png` and
+    /// `<name>.diff.png`.
+    pub fn assert_matches(&self, path: impl AsRef<Path>, draw: impl FnOnce(&mut Graphics)) {
+        let path = path.as_ref();
+        let actual = self.render(draw);
+
+        if std::env::var_os(UPDATE_ENV_VAR).is_some() {
+            if let Some(dir) = path.parent() {
+                std::fs::create_dir_all(dir).unwrap();
+            }
+            write_png(path, &actual, self.width, self.height);
+            return;
+        }
+        if !path.exists() {
+            panic!(
+                "snapshot {} does not exist, rerun with {} set to create it",
+                path.display(),
+                UPDATE_ENV_VAR
+            );
+        }
+
+        let (expected, width, height) = read_png(path);
+        let actual_path = sibling_path(path, "actual");
+        let diff_path = sibling_path(path, "diff");
+        if (width, height) != (self.width, self.height) {
+            write_png(&actual_path, &actual, self.width, self.height);
+            panic!(
+                "snapshot {} is {}x{} but rendered {}x{}",
+                path.display(),
+                width,
+                height,
+                self.width,
+                self.height
+            );
+        }
+
+        let (n_mismatched, diff) = diff_images(&expected, &actual, self.tolerance);
+        if n_mismatched > 0 {
+            write_png(&actual_path, &actual, self.width, self.height);
+            write_png(&diff_path, &diff, self.width, self.height);
+            panic!(
+                "snapshot {} differs in {} pixels, see {}",
+                path.display(),
+                n_mismatched,
+                diff_path.display()
+            );
+        }
+        let _ = std::fs::remove_file(actual_path);
+        let _ = std::fs::remove_file(diff_path);
+    }
+}
+
+/// Compares two RGBA buffers of equal size. Returns the number of pixels
+/// whose channels differ by more than `tolerance`, and a diff image with
+/// those pixels in red over a faded grayscale copy of `expected`.
+pub fn diff_images(expected: &[u8], actual: &[u8], tolerance: u8) -> (usize, Vec<u8>) {
+    let mut n_mismatched = 0;
+    let mut diff = Vec::with_capacity(expected.len());
+    for (e, a) in expected.chunks(4).zip(actual.chunks(4)) {
+        let mismatched = e.iter().zip(a).any(|(&x, &y)| x.abs_diff(y) > tolerance);
+        if mismatched {
+            n_mismatched += 1;
+            diff.extend_from_slice(&[0xff, 0, 0, 0xff]);
+        } else {
+            let luma = (e[0] as u32 + e[1] as u32 + e[2] as u32) / 3;
+            let faded = (luma * e[3] as u32 / 255 / 4) as u8;
+            diff.extend_from_slice(&[faded, faded, faded, 0xff]);
+        }
+    }
+    (n_mismatched, diff)
+}
+
+pub fn write_png(path: &Path, data: &[u8], width: u32, height: u32) {
+    image::RgbaImage::from_raw(width, height, data.to_vec())
+        .expect("image data does not match its size")
+        .save_with_format(path, image::ImageFormat::Png)
+        .unwrap();
+}
+
+pub fn read_png(path: &Path) -> (Vec<u8>, u32, u32) {
+    let image = image::open(path).unwrap().into_rgba8();
+    let (width, height) = image.dimensions();
+    (image.into_raw(), width, height)
+}
+
+fn sibling_path(path: &Path, suffix: &str) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    path.with_file_name(format!("{}.{}.png", stem, suffix))
+}
@@ -0,0 +1,1062 @@
+mod common;
+
+use common::Snapshot;
+use diode::graphics::*;
+use lyon::math::point;
+use lyon::path::builder::BorderRadii;
+use lyon::path::Path;
+use lyon::tessellation::{LineCap, LineJoin};
+
+fn snapshot_path(name: &str) -> String {
+    format!(
+        "{}/tests/snapshots/{}.png",
+        env!("CARGO_MANIFEST_DIR"),
+        name
+    )
+}
+
+fn encode_png(image: &image::RgbaImage) -> Vec<u8> {
+    let mut png = vec![];
+    image
+        .write_to(
+            &mut std::io::Cursor::new(&mut png),
+            image::ImageOutputFormat::Png,
+        )
+        .unwrap();
+    png
+}
+
+/// Vertices of `dst` mapped to `uv`, to be drawn with `QUAD_INDICES`.
+fn quad(dst: Rect, uv: Rect, color: [f32; 4]) -> [Vertex; 4] {
+    [
+        ([dst.x1, dst.y1], [uv.x1, uv.y1]),
+        ([dst.x1, dst.y2], [uv.x1, uv.y2]),
+        ([dst.x2, dst.y2], [uv.x2, uv.y2]),
+        ([dst.x2, dst.y1], [uv.x2, uv.y1]),
+    ]
+    .map(|(pos, uv)| Vertex { pos, uv, color })
+}
+
+const QUAD_INDICES: [u32; 6] = [0, 1, 2, 0, 2, 3];
+
+#[test]
+fn rects_and_lines() {
+    Snapshot::default().assert_matches(snapshot_path("rects_and_lines"), |gfx| {
+        // a `&Color` still converts into `Paint`
+        let red: &Color = &Color::rgba(1.0, 0.0, 0.0, 0.5);
+        gfx.fill_rect(20.0, 30.0, 120.0, 180.0, red);
+        gfx.draw_rect(
+            20.0,
+            30.0,
+            120.0,
+            180.0,
+            &StrokeStyle::default(),
+            Color::rgb(1.0, 1.0, 1.0),
+        );
+        gfx.fill_rect(60.0, 60.0, 220.0, 220.0, Color::rgba(0.0, 1.0, 0.0, 0.5));
+        gfx.draw_line(
+            20.0,
+            30.0,
+            220.0,
+            220.0,
+            &StrokeStyle::default(),
+            Color::rgb(1.0, 1.0, 0.0),
+        );
+        gfx.fill_tri(
+            10.0,
+            240.0,
+            60.0,
+            200.0,
+            110.0,
+            240.0,
+            Color::rgb(0.0, 0.0, 1.0),
+        );
+    });
+}
+
+#[test]
+fn paths() {
+    Snapshot::default().assert_matches(snapshot_path("paths"), |gfx| {
+        let mut builder = Path::builder();
+        builder.begin(point(30.0, 30.0));
+        builder.line_to(point(130.0, 60.0));
+        builder.line_to(point(200.0, 220.0));
+        builder.close();
+        let path = builder.build();
+        gfx.fill_path(path.clone(), Color::rgb(0.0, 1.0, 1.0));
+        gfx.draw_path(path, &StrokeStyle::default(), Color::rgb(1.0, 1.0, 1.0));
+    });
+}
+
+#[test]
+fn text() {
+    Snapshot::default().assert_matches(snapshot_path("text"), |gfx| {
+        gfx.draw_text(
+            "hello world",
+            24.0,
+            10.0,
+            10.0,
+            None,
+            None,
+            &Color::rgb(1.0, 1.0, 1.0),
+        );
+        gfx.draw_text(
+            "the quick brown fox jumps over the lazy dog",
+            14.0,
+            10.0,
+            60.0,
+            Some(200.0),
+            None,
+            &Color::rgb(1.0, 0.5, 0.0),
+        );
+        gfx.draw_fitted_text_line(
+            "the quick brown fox jumps over the lazy dog",
+            12.0,
+            10.0,
+            200.0,
+            150.0,
+            &Color::rgb(0.5, 0.5, 1.0),
+        );
+    });
+}
+
+#[test]
+fn gradients() {
+    let stops = vec![
+        GradientStop::new(0.0, Color::rgb(1.0, 0.0, 0.0)),
+        GradientStop::new(0.5, Color::rgb(0.0, 1.0, 0.0)),
+        GradientStop::new(1.0, Color::rgb(0.0, 0.0, 1.0)),
+    ];
+    Snapshot::default().assert_matches(snapshot_path("gradients"), |gfx| {
+        gfx.fill_rect(
+            10.0,
+            10.0,
+            118.0,
+            118.0,
+            Paint::LinearGradient {
+                x1: 10.0,
+                y1: 10.0,
+                x2: 118.0,
+                y2: 118.0,
+                stops: stops.clone(),
+            },
+        );
+        gfx.fill_circle(
+            192.0,
+            64.0,
+            54.0,
+            Paint::RadialGradient {
+                x: 192.0,
+                y: 64.0,
+                radius: 54.0,
+                stops: stops.clone(),
+            },
+        );
+        gfx.push_transform();
+        gfx.translate(128.0, 192.0);
+        gfx.rotate(0.5);
+        gfx.draw_rect(
+            -50.0,
+            -40.0,
+            50.0,
+            40.0,
+            &StrokeStyle::new(12.0),
+            Paint::ConicGradient {
+                x: 0.0,
+                y: 0.0,
+                angle: 0.0,
+                stops,
+            },
+        );
+        gfx.pop_transform();
+    });
+}
+
+#[test]
+fn images() {
+    let checker = image::RgbaImage::from_fn(8, 8, |x, y| {
+        if (x + y) % 2 == 0 {
+            image::Rgba([255, 255, 255, 255])
+        } else {
+            image::Rgba([255, 0, 0, 255])
+        }
+    });
+    let png = encode_png(&checker);
+
+    Snapshot::default().assert_matches(snapshot_path("images"), |gfx| {
+        let image = gfx.load_image(&png).unwrap();
+        gfx.draw_image(
+            &image,
+            None,
+            Rect::new(10.0, 10.0, 118.0, 118.0),
+            &Color::rgb(1.0, 1.0, 1.0),
+        );
+        gfx.draw_image(
+            &image,
+            Some(Rect::new(2.0, 2.0, 6.0, 6.0)),
+            Rect::new(138.0, 10.0, 246.0, 118.0),
+            &Color::rgba(0.0, 1.0, 1.0, 0.5),
+        );
+    });
+}
+
+#[test]
+fn nine_slice() {
+    // blue corners, green edges and a white center with a red dot
+    let frame = image::RgbaImage::from_fn(12, 12, |x, y| {
+        let edge_x = !(4..8).contains(&x);
+        let edge_y = !(4..8).contains(&y);
+        match (edge_x, edge_y) {
+            (true, true) => image::Rgba([0, 0, 255, 255]),
+            (true, false) | (false, true) => image::Rgba([0, 255, 0, 255]),
+            _ if (x, y) == (5, 5) => image::Rgba([255, 0, 0, 255]),
+            _ => image::Rgba([255, 255, 255, 255]),
+        }
+    });
+    let png = encode_png(&frame);
+
+    Snapshot::default().assert_matches(snapshot_path("nine_slice"), |gfx| {
+        let image = gfx.load_image(&png).unwrap();
+        let insets = Insets::uniform(4.0);
+        let white = Color::rgb(1.0, 1.0, 1.0);
+        gfx.draw_nine_slice(&image, &insets, Rect::new(10.0, 10.0, 118.0, 70.0), &white);
+        gfx.draw_nine_slice_tiled(&image, &insets, Rect::new(138.0, 10.0, 246.0, 70.0), &white);
+        // smaller than the corners
+        gfx.draw_nine_slice(&image, &insets, Rect::new(10.0, 90.0, 16.0, 96.0), &white);
+    });
+}
+
+#[test]
+fn blend_modes() {
+    Snapshot::default().assert_matches(snapshot_path("blend_modes"), |gfx| {
+        gfx.fill_rect(0.0, 0.0, 256.0, 128.0, Color::rgb(0.2, 0.4, 0.8));
+        gfx.fill_rect(0.0, 128.0, 256.0, 256.0, Color::rgb(0.9, 0.8, 0.3));
+        let modes = [
+            BlendMode::Normal,
+            BlendMode::Additive,
+            BlendMode::Multiply,
+            BlendMode::Screen,
+            BlendMode::Replace,
+        ];
+        for (i, mode) in modes.into_iter().enumerate() {
+            gfx.set_blend_mode(mode);
+            let x = 28.0 + i as f32 * 50.0;
+            gfx.fill_circle(x, 96.0, 20.0, Color::rgb(1.0, 0.5, 0.0));
+            gfx.fill_circle(x, 160.0, 20.0, Color::rgba(1.0, 0.5, 0.0, 0.5));
+        }
+        gfx.set_blend_mode(BlendMode::Normal);
+    });
+}
+
+#[test]
+fn materials() {
+    let stripes = "
+        struct Stripes {
+            color: vec4<f32>,
+            period: f32,
+        };
+        @group(2) @binding(0)
+        var<uniform> stripes: Stripes;
+
+        @fragment
+        fn fs_main(vertex: VertexOutput) -> @location(0) vec4<f32> {
+            if fract(vertex.pos.y / stripes.period) < 0.5 {
+                return stripes.color * vertex.color;
+            }
+            return vertex.color;
+        }
+    ";
+    let uniforms = |color: [f32; 4], period: f32| {
+        let mut data = bytemuck::cast_slice(&color).to_vec();
+        data.extend_from_slice(bytemuck::bytes_of(&period));
+        data
+    };
+
+    Snapshot::default().assert_matches(snapshot_path("materials"), |gfx| {
+        let material = gfx
+            .register_material(stripes, &uniforms([1.0, 0.0, 0.0, 1.0], 8.0))
+            .unwrap();
+        let no_uv = Rect::new(0.0, 0.0, 0.0, 0.0);
+        gfx.add_geom_with_material(
+            &quad(Rect::new(10.0, 10.0, 118.0, 246.0), no_uv, [1.0; 4]),
+            &QUAD_INDICES,
+            material,
+        );
+        gfx.fill_rect(138.0, 10.0, 246.0, 118.0, Color::rgb(0.0, 0.0, 1.0));
+        gfx.set_material_uniforms(material, &uniforms([0.0, 1.0, 0.0, 1.0], 16.0));
+        gfx.add_geom_with_material(
+            &quad(Rect::new(138.0, 138.0, 246.0, 246.0), no_uv, [1.0; 4]),
+            &QUAD_INDICES,
+            material,
+        );
+    });
+}
+
+#[test]
+fn invalid_material() {
+    Snapshot::default().render(|gfx| {
+        let missing_entry_point = "
+            @fragment
+            fn main(vertex: VertexOutput) -> @location(0) vec4<f32> {
+                return vertex.color;
+            }
+        ";
+        assert!(gfx.register_material("not wgsl", &[]).is_err());
+        assert!(gfx.register_material(missing_entry_point, &[]).is_err());
+    });
+}
+
+#[test]
+fn layers() {
+    Snapshot::default().assert_matches(snapshot_path("layers"), |gfx| {
+        gfx.fill_rect(0.0, 0.0, 256.0, 256.0, Color::rgb(1.0, 1.0, 1.0));
+
+        // overlapping shapes faded one by one show through each other
+        gfx.fill_circle(50.0, 64.0, 30.0, Color::rgba(1.0, 0.0, 0.0, 0.5));
+        gfx.fill_circle(80.0, 64.0, 30.0, Color::rgba(0.0, 0.0, 1.0, 0.5));
+
+        // but not when faded as a group
+        gfx.begin_layer(100.0, 60.0);
+        gfx.fill_circle(30.0, 30.0, 30.0, Color::rgb(1.0, 0.0, 0.0));
+        gfx.fill_circle(60.0, 30.0, 30.0, Color::rgb(0.0, 0.0, 1.0));
+        let group = gfx.end_layer();
+        gfx.draw_image(
+            &group,
+            None,
+            Rect::new(140.0, 34.0, 240.0, 94.0),
+            &Color::rgba(1.0, 1.0, 1.0, 0.5),
+        );
+
+        // nested layers, composited under a transform
+        gfx.begin_layer(64.0, 64.0);
+        gfx.fill_rect(0.0, 0.0, 64.0, 64.0, Color::rgb(0.0, 0.6, 0.0));
+        gfx.begin_layer(32.0, 32.0);
+        gfx.fill_circle(16.0, 16.0, 16.0, Color::rgba(1.0, 1.0, 0.0, 0.5));
+        let inner = gfx.end_layer();
+        gfx.draw_image(
+            &inner,
+            None,
+            Rect::new(16.0, 16.0, 48.0, 48.0),
+            &Color::rgb(1.0, 1.0, 1.0),
+        );
+        let outer = gfx.end_layer();
+        gfx.push_transform();
+        gfx.translate(128.0, 180.0);
+        gfx.rotate(0.4);
+        gfx.draw_image(
+            &outer,
+            None,
+            Rect::new(-32.0, -32.0, 32.0, 32.0),
+            &Color::rgb(1.0, 1.0, 1.0),
+        );
+        gfx.pop_transform();
+    });
+}
+
+#[test]
+fn layer_pool() {
+    Snapshot::default().assert_matches(snapshot_path("layer_pool"), |gfx| {
+        gfx.begin_layer(64.0, 64.0);
+        gfx.fill_circle(32.0, 32.0, 32.0, Color::rgb(1.0, 0.0, 0.0));
+        let kept = gfx.end_layer();
+        gfx.keep_layer(&kept);
+        gfx.begin_layer(64.0, 64.0);
+        gfx.fill_rect(0.0, 0.0, 64.0, 64.0, Color::rgb(0.0, 1.0, 0.0));
+        gfx.end_layer();
+        gfx.commit_geom();
+        gfx.render_to_image();
+
+        // the second layer's texture is reused, the kept one is not
+        gfx.begin_layer(64.0, 64.0);
+        gfx.fill_rect(16.0, 16.0, 48.0, 48.0, Color::rgb(0.0, 0.0, 1.0));
+        let reused = gfx.end_layer();
+        gfx.draw_image(
+            &kept,
+            None,
+            Rect::new(0.0, 0.0, 64.0, 64.0),
+            &Color::rgb(1.0, 1.0, 1.0),
+        );
+        gfx.draw_image(
+            &reused,
+            None,
+            Rect::new(64.0, 0.0, 128.0, 64.0),
+            &Color::rgb(1.0, 1.0, 1.0),
+        );
+
+        // more layers than fit in the initial uniform buffer
+        for i in 0..96 {
+            let (x, y) = ((i % 16) as f32 * 16.0, 96.0 + (i / 16) as f32 * 16.0);
+            gfx.begin_layer(12.0, 12.0);
+            gfx.fill_rect(0.0, 0.0, 12.0, 12.0, Color::rgb(i as f32 / 96.0, 0.5, 0.5));
+            let layer = gfx.end_layer();
+            gfx.draw_image(
+                &layer,
+                None,
+                Rect::new(x, y, x + 12.0, y + 12.0),
+                &Color::rgb(1.0, 1.0, 1.0),
+            );
+        }
+    });
+}
+
+#[test]
+fn shadows_and_blur() {
+    Snapshot::default().assert_matches(snapshot_path("shadows_and_blur"), |gfx| {
+        gfx.fill_rect(0.0, 0.0, 256.0, 256.0, Color::rgb(1.0, 1.0, 1.0));
+
+        let card = Rect::new(24.0, 24.0, 104.0, 104.0);
+        gfx.draw_box_shadow(card, 8.0, 16.0, 0.0, &Color::rgba(0.0, 0.0, 0.0, 0.6));
+        gfx.fill_rounded_rect(card, &BorderRadii::new(8.0), Color::rgb(0.9, 0.9, 1.0));
+
+        let card = Rect::new(152.0, 24.0, 232.0, 104.0);
+        gfx.draw_box_shadow(card, 0.0, 0.0, 6.0, &Color::rgb(1.0, 0.5, 0.0));
+        gfx.push_transform();
+        gfx.translate(192.0, 64.0);
+        gfx.rotate(0.3);
+        gfx.draw_box_shadow(
+            Rect::new(-30.0, -30.0, 30.0, 30.0),
+            4.0,
+            10.0,
+            0.0,
+            &Color::rgb(0.0, 0.0, 0.0),
+        );
+        gfx.pop_transform();
+
+        gfx.begin_layer(256.0, 128.0);
+        for i in 0..8 {
+            let x = 16.0 + i as f32 * 30.0;
+            gfx.fill_rect(x, 16.0, x + 15.0, 112.0, Color::rgb(0.0, 0.3, 0.8));
+        }
+        gfx.blur_layer(4.0);
+        let frosted = gfx.end_layer();
+        gfx.draw_image(
+            &frosted,
+            None,
+            Rect::new(0.0, 128.0, 256.0, 256.0),
+            &Color::rgb(1.0, 1.0, 1.0),
+        );
+    });
+}
+
+#[test]
+fn wide_blur() {
+    Snapshot::default().assert_matches(snapshot_path("wide_blur"), |gfx| {
+        gfx.fill_rect(0.0, 0.0, 256.0, 256.0, Color::rgb(1.0, 1.0, 1.0));
+        // limited to the widest blur a pass samples
+        gfx.begin_layer(256.0, 256.0);
+        gfx.fill_rect(96.0, 16.0, 160.0, 240.0, Color::rgb(0.8, 0.1, 0.3));
+        gfx.blur_layer(40.0);
+        let blurred = gfx.end_layer();
+        gfx.draw_image(
+            &blurred,
+            None,
+            Rect::new(0.0, 0.0, 256.0, 256.0),
+            &Color::rgb(1.0, 1.0, 1.0),
+        );
+    });
+}
+
+#[test]
+fn clear_color() {
+    Snapshot::default().assert_matches(snapshot_path("clear_color"), |gfx| {
+        gfx.set_clear_color(Some(Color::rgb(0.1, 0.1, 0.3)));
+        gfx.fill_circle(128.0, 128.0, 64.0, Color::rgba(1.0, 1.0, 1.0, 0.5));
+    });
+}
+
+#[test]
+fn msaa() {
+    let draw = |gfx: &mut Graphics| {
+        gfx.set_msaa_samples(4);
+        gfx.fill_rect(0.0, 0.0, 256.0, 256.0, Color::rgb(1.0, 1.0, 1.0));
+        gfx.fill_tri(
+            16.0,
+            240.0,
+            128.0,
+            16.0,
+            240.0,
+            200.0,
+            Color::rgb(0.0, 0.0, 0.0),
+        );
+        gfx.draw_line(
+            16.0,
+            16.0,
+            240.0,
+            100.0,
+            &StrokeStyle::new(2.0),
+            Color::rgb(0.8, 0.0, 0.0),
+        );
+    };
+    // diagonal edges get intermediate coverage along them
+    let frame = Snapshot::default().render(draw);
+    assert!(frame
+        .chunks(4)
+        .any(|pixel| pixel[1] > 0 && pixel[1] < 255 && pixel[0] == pixel[1]));
+    Snapshot::default().assert_matches(snapshot_path("msaa"), draw);
+}
+
+#[test]
+fn no_clear_color_with_msaa() {
+    Snapshot::default().assert_matches(snapshot_path("no_clear_color_with_msaa"), |gfx| {
+        gfx.set_msaa_samples(4);
+        gfx.set_clear_color(Some(Color::rgb(0.1, 0.1, 0.3)));
+        gfx.fill_rect(16.0, 16.0, 112.0, 112.0, Color::rgb(1.0, 0.0, 0.0));
+        gfx.commit_geom();
+        gfx.render_to_image();
+
+        // drawn over the new, transparent target rather than the previous
+        // frame left in the multisampled attachment
+        gfx.set_clear_color(None);
+        gfx.fill_circle(160.0, 160.0, 64.0, Color::rgb(0.0, 0.8, 0.2));
+    });
+}
+
+#[test]
+fn z_index() {
+    Snapshot::default().assert_matches(snapshot_path("z_index"), |gfx| {
+        // added first, drawn on top
+        gfx.set_z_index(1);
+        gfx.fill_rect(20.0, 20.0, 100.0, 100.0, Color::rgb(1.0, 0.0, 0.0));
+        gfx.set_z_index(0);
+        gfx.fill_rect(60.0, 60.0, 140.0, 140.0, Color::rgb(0.0, 0.0, 1.0));
+
+        // clip paths apply across z-indices
+        let mut builder = Path::builder();
+        builder.begin(point(128.0, 150.0));
+        builder.line_to(point(236.0, 150.0));
+        builder.line_to(point(182.0, 240.0));
+        builder.close();
+        gfx.push_clip_path(builder.build());
+        gfx.fill_rect(100.0, 140.0, 256.0, 256.0, Color::rgb(0.0, 0.6, 0.0));
+        gfx.set_z_index(2);
+        gfx.fill_rect(150.0, 180.0, 256.0, 256.0, Color::rgb(1.0, 1.0, 0.0));
+        gfx.set_z_index(0);
+        gfx.pop_clip();
+
+        // and are undone for all of them
+        gfx.set_z_index(2);
+        gfx.fill_circle(60.0, 200.0, 30.0, Color::rgb(1.0, 0.0, 1.0));
+        gfx.fill_circle(182.0, 160.0, 10.0, Color::rgb(0.0, 1.0, 1.0));
+        gfx.set_z_index(0);
+    });
+}
+
+#[test]
+fn sprites() {
+    // left half white, right half red
+    let data = (0..8 * 8)
+        .flat_map(|i| {
+            if i % 8 < 4 {
+                [255, 255, 255, 255]
+            } else {
+                [255, 0, 0, 255]
+            }
+        })
+        .collect::<Vec<u8>>();
+
+    Snapshot::default().assert_matches(snapshot_path("sprites"), |gfx| {
+        let region = gfx.add_texture(&data, 8, 8);
+        let uv = region.uv;
+        gfx.fill_rect(0.0, 0.0, 256.0, 150.0, Color::rgb(0.3, 0.3, 0.3));
+        let mut batch = SpriteBatch::new(region.texture);
+        for i in 0..16 {
+            let mut sprite = Sprite::new(
+                24.0 + (i % 4) as f32 * 56.0,
+                24.0 + (i / 4) as f32 * 40.0,
+                32.0,
+                16.0,
+                uv,
+            );
+            sprite.rotation = i as f32 * std::f32::consts::PI / 8.0;
+            sprite.color = Color::rgba(1.0, 1.0 - i as f32 / 16.0, 1.0, 1.0);
+            batch.push(sprite);
+        }
+        gfx.draw_sprites(&batch);
+
+        // sprites keep their place among other geometry
+        gfx.fill_rect(0.0, 150.0, 256.0, 256.0, Color::rgb(0.0, 0.0, 0.5));
+        batch.clear();
+        batch.push(Sprite::new(0.0, 0.0, 48.0, 48.0, uv));
+        gfx.push_clip_rect(Rect::new(0.0, 160.0, 256.0, 240.0));
+        gfx.push_transform();
+        gfx.translate(128.0, 200.0);
+        gfx.rotate(std::f32::consts::PI / 4.0);
+        gfx.draw_sprites(&batch);
+        gfx.translate(48.0, 0.0);
+        gfx.scale(2.0, 2.0);
+        gfx.draw_sprites(&batch);
+        gfx.pop_transform();
+        gfx.pop_clip();
+    });
+}
+
+#[test]
+fn sprite_sheets() {
+    // 4x2 frames of 8x8 pixels, each a different color with a white corner
+    let colors = [
+        [255, 0, 0],
+        [0, 255, 0],
+        [0, 0, 255],
+        [255, 255, 0],
+        [255, 0, 255],
+        [0, 255, 255],
+        [255, 128, 0],
+        [128, 0, 255],
+    ];
+    let sheet_image = image::RgbaImage::from_fn(32, 16, |x, y| {
+        if x % 8 < 2 && y % 8 < 2 {
+            image::Rgba([255, 255, 255, 255])
+        } else {
+            let [r, g, b] = colors[(y / 8 * 4 + x / 8) as usize];
+            image::Rgba([r, g, b, 255])
+        }
+    });
+    let png = encode_png(&sheet_image);
+
+    Snapshot::default().assert_matches(snapshot_path("sprite_sheets"), |gfx| {
+        let image = gfx.load_image(&png).unwrap();
+        let sheet = SpriteSheet::from_grid(image, 4, 2);
+        let white = Color::rgb(1.0, 1.0, 1.0);
+        for frame in 0..sheet.n_frames() {
+            let x = frame as f32 * 32.0;
+            gfx.draw_sheet_frame(&sheet, frame, Rect::new(x, 0.0, x + 28.0, 28.0), &white);
+        }
+
+        // a looping animation over some of the frames, at 10 fps
+        let mut animation = Animation::new(sheet, &[1, 3, 5], 10.0);
+        for i in 0..8 {
+            let x = i as f32 * 32.0;
+            gfx.draw_animation(&animation, Rect::new(x, 64.0, x + 28.0, 92.0), &white);
+            animation.update(0.05);
+        }
+
+        // a one-shot animation stops on its last frame
+        let mut animation = Animation::from_sheet(sheet, 4.0);
+        animation.looping = false;
+        for i in 0..8 {
+            let x = i as f32 * 32.0;
+            gfx.draw_animation(&animation, Rect::new(x, 128.0, x + 28.0, 156.0), &white);
+            animation.update(0.5);
+        }
+        assert!(animation.is_finished());
+        assert_eq!(animation.current_frame(), 7);
+    });
+}
+
+#[test]
+fn tile_map() {
+    // 2x2 tiles of 4x4 pixels: red, green, blue and white
+    let colors = [[255, 0, 0], [0, 255, 0], [0, 0, 255], [255, 255, 255]];
+    let tileset_image = image::RgbaImage::from_fn(8, 8, |x, y| {
+        let [r, g, b] = colors[(y / 4 * 2 + x / 4) as usize];
+        image::Rgba([r, g, b, 255])
+    });
+    let png = encode_png(&tileset_image);
+
+    Snapshot::default().assert_matches(snapshot_path("tile_map"), |gfx| {
+        let image = gfx.load_image(&png).unwrap();
+        let tileset = SpriteSheet::from_grid(image, 2, 2);
+        // larger than the frame and spanning several chunks
+        let mut map = TileMap::new(tileset, 40, 40, 8.0, 8.0);
+        for y in 0..map.height() {
+            for x in 0..map.width() {
+                if (x / 3 + y / 5) % 5 != 0 {
+                    map.set_tile(x, y, Some(((x + 2 * y) % 4) as usize));
+                }
+            }
+        }
+
+        gfx.push_transform();
+        gfx.translate(-20.0, -12.0);
+        gfx.draw_tile_map(&mut map);
+        gfx.pop_transform();
+
+        // changing a tile rebuilds its chunk without affecting what was
+        // already drawn
+        for x in 0..map.width() {
+            map.set_tile(x, 20, Some(3));
+        }
+        gfx.push_clip_rect(Rect::new(128.0, 128.0, 256.0, 256.0));
+        gfx.push_transform();
+        gfx.translate(128.0, 128.0);
+        gfx.rotate(std::f32::consts::PI / 8.0);
+        gfx.scale(0.5, 0.5);
+        gfx.draw_tile_map(&mut map);
+        gfx.pop_transform();
+        gfx.pop_clip();
+
+        gfx.fill_circle(128.0, 128.0, 24.0, Color::rgba(0.0, 0.0, 0.0, 0.5));
+        gfx.free_tile_map(map);
+    });
+}
+
+#[test]
+fn srgb_colors() {
+    let snapshot = Snapshot {
+        width: 4,
+        height: 1,
+        ..Default::default()
+    };
+    let pixels = snapshot.render(|gfx| {
+        gfx.set_clear_color(Some(Color::rgb(0.0, 0.0, 0.0)));
+        gfx.fill_rect(0.0, 0.0, 1.0, 1.0, Color::rgb(0.5, 0.5, 0.5));
+        gfx.fill_rect(1.0, 0.0, 2.0, 1.0, Color::from_linear(0.5, 0.5, 0.5, 1.0));
+        // blending happens in linear light
+        gfx.fill_rect(2.0, 0.0, 3.0, 1.0, Color::rgba(1.0, 1.0, 1.0, 0.5));
+        gfx.fill_rect(3.0, 0.0, 4.0, 1.0, Color::from_srgb(0.2, 0.4, 0.6, 1.0));
+    });
+    let expected = [
+        [128, 128, 128],
+        [188, 188, 188],
+        [188, 188, 188],
+        [51, 102, 153],
+    ];
+    for (pixel, expected) in pixels.chunks(4).zip(expected) {
+        for (&actual, expected) in pixel.iter().zip(expected) {
+            assert!(
+                actual.abs_diff(expected) <= 1,
+                "{:?} != {:?}",
+                pixel,
+                expected
+            );
+        }
+    }
+    let [r, g, b, a] = Color::from_linear(0.25, 0.5, 1.0, 0.5).to_linear();
+    assert!((r - 0.25).abs() < 1e-5 && (g - 0.5).abs() < 1e-5 && (b - 1.0).abs() < 1e-5);
+    assert_eq!(a, 0.5);
+}
+
+#[test]
+fn buffer_growth() {
+    // far more vertices and indices than the initial buffers hold
+    Snapshot::default().assert_matches(snapshot_path("buffer_growth"), |gfx| {
+        for y in 0..100 {
+            for x in 0..200 {
+                let (x1, y1) = (x as f32 * 1.28, y as f32 * 2.56);
+                gfx.fill_rect(
+                    x1,
+                    y1,
+                    x1 + 1.0,
+                    y1 + 2.0,
+                    Color::rgb(x as f32 / 200.0, y as f32 / 100.0, 0.5),
+                );
+            }
+        }
+    });
+}
+
+#[test]
+fn texture_batches() {
+    // too large for the atlas, so it gets a texture of its own
+    let large = image::RgbaImage::from_fn(600, 600, |x, y| {
+        image::Rgba([(x * 255 / 600) as u8, (y * 255 / 600) as u8, 255, 255])
+    });
+    let png = encode_png(&large);
+
+    Snapshot::default().assert_matches(snapshot_path("texture_batches"), |gfx| {
+        let image = gfx.load_image(&png).unwrap();
+        let region = gfx.add_texture(&[255, 128, 0, 255], 1, 1);
+        let (texel, atlas) = (region.uv, region.texture);
+        let white = Color::rgb(1.0, 1.0, 1.0);
+        // overlapping draws alternating between textures keep their order
+        for i in 0..6 {
+            let x = 16.0 + i as f32 * 32.0;
+            let dst = Rect::new(x, 16.0 + i as f32 * 16.0, x + 64.0, 80.0 + i as f32 * 16.0);
+            if i % 2 == 0 {
+                gfx.draw_image(&image, None, dst, &white);
+            } else {
+                let [u, v] = [(texel.x1 + texel.x2) / 2.0, (texel.y1 + texel.y2) / 2.0];
+                let vertices = quad(dst, Rect::new(u, v, u, v), [1.0; 4]);
+                gfx.add_geom_with_texture(&vertices, &QUAD_INDICES, atlas);
+            }
+        }
+        gfx.draw_text("batches", 24.0, 16.0, 200.0, None, None, &white);
+        gfx.fill_rect(40.0, 190.0, 120.0, 230.0, Color::rgba(0.0, 0.0, 0.0, 0.5));
+    });
+}
+
+#[test]
+fn atlas_pages() {
+    Snapshot::default().assert_matches(snapshot_path("atlas_pages"), |gfx| {
+        // three of these can't share a page
+        let colors = [[255, 0, 0, 255], [0, 255, 0, 255], [0, 0, 255, 255]];
+        let regions = colors.map(|color| gfx.add_texture(&color.repeat(600 * 600), 600, 600));
+        assert!(
+            regions[0].texture != regions[1].texture && regions[1].texture != regions[2].texture
+        );
+        for (i, region) in regions.iter().enumerate() {
+            let x = 16.0 + i as f32 * 80.0;
+            let vertices = quad(Rect::new(x, 16.0, x + 64.0, 80.0), region.uv, [1.0; 4]);
+            gfx.add_geom_with_texture(&vertices, &QUAD_INDICES, region.texture);
+        }
+
+        // glyphs rasterized this large overflow a glyph page
+        gfx.fill_rect(0.0, 96.0, 256.0, 256.0, Color::rgb(0.2, 0.2, 0.3));
+        gfx.push_transform();
+        gfx.scale(0.1, 0.1);
+        gfx.draw_text(
+            "ABCDEFGHIJKLMNOPQRSTUVWXYZ",
+            400.0,
+            160.0,
+            1000.0,
+            Some(2400.0),
+            None,
+            &Color::rgb(1.0, 1.0, 1.0),
+        );
+        gfx.pop_transform();
+    });
+}
+
+#[test]
+fn clip_rects() {
+    Snapshot::default().assert_matches(snapshot_path("clip_rects"), |gfx| {
+        gfx.push_clip_rect(Rect::new(32.0, 32.0, 160.0, 160.0));
+        gfx.fill_rect(0.0, 0.0, 256.0, 256.0, Color::rgb(0.2, 0.2, 0.6));
+        // nested clips intersect with the enclosing ones
+        gfx.push_clip_rect(Rect::new(96.0, 96.0, 224.0, 224.0));
+        gfx.fill_circle(128.0, 128.0, 100.0, Color::rgb(1.0, 0.5, 0.0));
+        gfx.push_clip_rect(Rect::new(200.0, 0.0, 256.0, 256.0));
+        gfx.fill_rect(0.0, 0.0, 256.0, 256.0, Color::rgb(1.0, 0.0, 0.0));
+        gfx.pop_clip();
+        gfx.pop_clip();
+        // popping restores the outer clip
+        gfx.draw_text(
+            "clipped text runs past its box",
+            20.0,
+            40.0,
+            40.0,
+            None,
+            None,
+            &Color::rgb(1.0, 1.0, 1.0),
+        );
+        gfx.pop_clip();
+        gfx.fill_rect(200.0, 200.0, 240.0, 240.0, Color::rgb(0.0, 0.8, 0.0));
+    });
+}
+
+#[test]
+fn clip_paths() {
+    Snapshot::default().assert_matches(snapshot_path("clip_paths"), |gfx| {
+        let mut builder = Path::builder();
+        builder.begin(point(64.0, 8.0));
+        builder.line_to(point(120.0, 120.0));
+        builder.line_to(point(8.0, 120.0));
+        builder.close();
+        let triangle = builder.build();
+
+        gfx.push_clip_path(triangle.clone());
+        gfx.fill_rect(0.0, 0.0, 128.0, 128.0, Color::rgb(0.2, 0.4, 1.0));
+        gfx.pop_clip();
+
+        // a path clip inside a rect clip, and a rect clip inside a path clip
+        gfx.push_clip_rect(Rect::new(128.0, 0.0, 256.0, 64.0));
+        gfx.push_transform();
+        gfx.translate(128.0, 0.0);
+        gfx.push_clip_path(triangle.clone());
+        gfx.pop_transform();
+        gfx.fill_rect(128.0, 0.0, 256.0, 128.0, Color::rgb(1.0, 0.5, 0.0));
+        gfx.pop_clip();
+        gfx.pop_clip();
+
+        gfx.push_transform();
+        gfx.translate(64.0, 128.0);
+        gfx.push_clip_path(triangle);
+        gfx.pop_transform();
+        gfx.push_clip_rect(Rect::new(0.0, 200.0, 256.0, 256.0));
+        gfx.fill_rect(0.0, 128.0, 256.0, 256.0, Color::rgb(0.0, 0.8, 0.3));
+        gfx.pop_clip();
+        // popping the rect restores the path clip alone
+        gfx.fill_circle(128.0, 180.0, 24.0, Color::rgb(0.6, 0.0, 0.6));
+        gfx.pop_clip();
+    });
+}
+
+#[test]
+fn transforms() {
+    Snapshot::default().assert_matches(snapshot_path("transforms"), |gfx| {
+        let white = Color::rgb(1.0, 1.0, 1.0);
+        gfx.fill_rect(0.0, 0.0, 256.0, 256.0, Color::rgb(0.1, 0.1, 0.2));
+        gfx.push_transform();
+        gfx.translate(128.0, 128.0);
+        for i in 0..8 {
+            gfx.push_transform();
+            gfx.rotate(i as f32 * std::f32::consts::PI / 4.0);
+            gfx.translate(72.0, 0.0);
+            // non-uniform scale, with paths tessellated finely enough
+            gfx.scale(4.0, 1.0);
+            gfx.fill_circle(0.0, 0.0, 6.0, Color::rgb(i as f32 / 8.0, 0.5, 1.0));
+            gfx.pop_transform();
+        }
+        gfx.scale(0.5, 0.5);
+        gfx.draw_rect(-60.0, -60.0, 60.0, 60.0, &StrokeStyle::new(4.0), white);
+        gfx.rotate(-std::f32::consts::PI / 6.0);
+        gfx.draw_text("rotated", 32.0, -50.0, -10.0, None, None, &white);
+        gfx.pop_transform();
+        // popping restores the identity
+        gfx.fill_rect(0.0, 0.0, 16.0, 16.0, white);
+    });
+}
+
+#[test]
+fn stroke_styles() {
+    Snapshot::default().assert_matches(snapshot_path("stroke_styles"), |gfx| {
+        let white = Color::rgb(1.0, 1.0, 1.0);
+        gfx.fill_rect(0.0, 0.0, 256.0, 256.0, Color::rgb(0.1, 0.1, 0.2));
+
+        // widths
+        for (i, width) in [1.0, 2.0, 4.0, 8.0].into_iter().enumerate() {
+            let y = 12.0 + i as f32 * 14.0;
+            gfx.draw_line(10.0, y, 70.0, y, &StrokeStyle::new(width), white);
+        }
+
+        // caps, with a thin line marking the end points
+        for (i, cap) in [LineCap::Butt, LineCap::Square, LineCap::Round]
+            .into_iter()
+            .enumerate()
+        {
+            let y = 16.0 + i as f32 * 20.0;
+            let style = StrokeStyle {
+                cap,
+                ..StrokeStyle::new(12.0)
+            };
+            gfx.draw_line(100.0, y, 150.0, y, &style, Color::rgb(1.0, 0.6, 0.0));
+            gfx.draw_line(100.0, y, 150.0, y, &StrokeStyle::new(1.0), white);
+        }
+
+        // joins
+        for (i, join) in [LineJoin::Miter, LineJoin::Round, LineJoin::Bevel]
+            .into_iter()
+            .enumerate()
+        {
+            let x = 180.0 + i as f32 * 24.0;
+            let style = StrokeStyle {
+                join,
+                ..StrokeStyle::new(8.0)
+            };
+            gfx.draw_tri(x, 60.0, x + 8.0, 16.0, x + 16.0, 60.0, &style, white);
+        }
+
+        // a sharp corner mitered, then beveled by a lower miter limit
+        for (i, miter_limit) in [10.0, 1.0].into_iter().enumerate() {
+            let x = 20.0 + i as f32 * 60.0;
+            let mut builder = Path::builder();
+            builder.begin(point(x, 150.0));
+            builder.line_to(point(x + 20.0, 100.0));
+            builder.line_to(point(x + 40.0, 150.0));
+            builder.end(false);
+            let style = StrokeStyle {
+                miter_limit,
+                ..StrokeStyle::new(8.0)
+            };
+            gfx.draw_path(builder.build(), &style, Color::rgb(0.0, 0.8, 1.0));
+        }
+
+        // dashes, shifted by an offset
+        for (i, dash_offset) in [0.0, 5.0, 10.0].into_iter().enumerate() {
+            let y = 170.0 + i as f32 * 10.0;
+            let style = StrokeStyle {
+                dash_offset,
+                ..StrokeStyle::dashed(4.0, &[10.0, 5.0])
+            };
+            gfx.draw_line(10.0, y, 120.0, y, &style, white);
+        }
+
+        // dashes continue around the corners of a closed path, with caps
+        let style = StrokeStyle {
+            cap: LineCap::Round,
+            ..StrokeStyle::dashed(3.0, &[0.0, 8.0])
+        };
+        gfx.draw_rect(
+            150.0,
+            110.0,
+            240.0,
+            200.0,
+            &style,
+            Color::rgb(1.0, 0.3, 0.3),
+        );
+        gfx.draw_circle(195.0, 155.0, 30.0, &StrokeStyle::dashed(2.0, &[6.0]), white);
+
+        // far too many dashes for the length of the line fall back to a
+        // solid line
+        let style = StrokeStyle {
+            cap: LineCap::Square,
+            ..StrokeStyle::dashed(2.0, &[0.5, 3.5])
+        };
+        gfx.draw_line(0.0, 240.0, 1.0e8, 240.0, &style, white);
+    });
+}
+
+#[test]
+fn shapes() {
+    Snapshot::default().assert_matches(snapshot_path("shapes"), |gfx| {
+        let style = StrokeStyle::new(3.0);
+        let radii = BorderRadii {
+            top_left: 0.0,
+            top_right: 8.0,
+            bottom_left: 16.0,
+            bottom_right: 24.0,
+        };
+        gfx.fill_rounded_rect(
+            Rect::new(16.0, 16.0, 112.0, 96.0),
+            &radii,
+            Color::rgb(0.2, 0.6, 1.0),
+        );
+        gfx.draw_rounded_rect(
+            Rect::new(144.0, 16.0, 240.0, 96.0),
+            &radii,
+            &style,
+            Color::rgb(0.2, 0.6, 1.0),
+        );
+        gfx.fill_circle(40.0, 140.0, 24.0, Color::rgb(1.0, 0.5, 0.0));
+        gfx.draw_circle(104.0, 140.0, 24.0, &style, Color::rgb(1.0, 0.5, 0.0));
+        gfx.fill_ellipse(168.0, 140.0, 24.0, 12.0, Color::rgb(0.0, 0.8, 0.4));
+        gfx.draw_ellipse(224.0, 140.0, 12.0, 24.0, &style, Color::rgb(0.0, 0.8, 0.4));
+        let quarter = std::f32::consts::FRAC_PI_2;
+        gfx.draw_arc(
+            point(64.0, 216.0),
+            28.0,
+            -quarter,
+            3.0 * quarter,
+            &StrokeStyle::new(6.0),
+            Color::rgb(1.0, 0.2, 0.4),
+        );
+        gfx.fill_pie(
+            point(160.0, 216.0),
+            28.0,
+            0.0,
+            quarter,
+            Color::rgb(0.8, 0.8, 0.2),
+        );
+        gfx.draw_tri(
+            200.0,
+            240.0,
+            224.0,
+            192.0,
+            248.0,
+            240.0,
+            &style,
+            Color::rgb(0.5, 0.5, 0.5),
+        );
+    });
+}
+
+#[test]
+fn freed_image() {
+    // too large for the atlas, so the image gets a texture of its own
+    let gradient = image::RgbaImage::from_fn(600, 600, |x, y| {
+        image::Rgba([(x * 255 / 599) as u8, (y * 255 / 599) as u8, 128, 255])
+    });
+    let png = encode_png(&gradient);
+
+    Snapshot::default().assert_matches(snapshot_path("freed_image"), |gfx| {
+        let image = gfx.load_image(&png).unwrap();
+        gfx.draw_image(
+            &image,
+            None,
+            Rect::new(16.0, 16.0, 240.0, 240.0),
+            &Color::rgb(1.0, 1.0, 1.0),
+        );
+        // the frame that drew the image still renders it
+        gfx.free_image(image);
+    });
+}